@@ -22,12 +22,14 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     .add_statement(db, DbAction::Create)
     .add_statement(&users, DbAction::Create)
     .add_statement(&orders, DbAction::Create)
-    .add_statement(&users, DbAction::Drop);
+    .add_statement(&users, DbAction::Drop)
+    .allow_destructive();
     let client_conf = ConnectionConfig::default();
     let mongo = Mongo::new(client_conf).await.expect("Failed to connect");
     let cortex_config = CortexMongoConfig {
-        supported_db_versions: (semver::Version::new(0, 0, 1), semver::Version::new(0, 0, 1)),
+        supported_db_versions: (semver::Version::new(5, 0, 0), semver::Version::new(8, 0, 0)),
         execution_mode: ExecutionMode::Optimistic,
+        ..Default::default()
     };
     let cortex = CortexMongo::new(mongo, cortex_config).add_step(data);
     _ = cortex.execute().await.expect("Failed to execute");
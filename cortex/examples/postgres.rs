@@ -27,7 +27,8 @@ fn main() -> Result<()> {
     )
     .add_statement(&ts_db, DbAction::Create)
     .add_statement(&config_db, DbAction::Create)
-    .add_statement(&sales_db, DbAction::Create);
+    .add_statement(&sales_db, DbAction::Create)
+    .with_policy(ExistencePolicy::IfNotExists);
 
     let conf_db_steps = vec![Step::new(
         "Config Schema",
@@ -35,7 +36,15 @@ fn main() -> Result<()> {
         semver::Version::new(0, 0, 2),
     )
     .add_statement(&users, DbAction::Create)
-    .add_statement(&orders, DbAction::Create)];
+    .add_statement(&orders, DbAction::Create)
+    .add_statement(
+        &users,
+        DbAction::Insert(
+            Insert::new()
+                .add_row(Row::new().set("id", 1))
+                .on_conflict(OnConflict::DoNothing(vec![])),
+        ),
+    )];
     let sales_db_steps = vec![Step::new(
         "Sales Schema",
         StepType::Update,
@@ -63,12 +72,24 @@ fn main() -> Result<()> {
         execution_mode: ExecutionMode::Transactional,
         supported_db_versions: (
             semver::Version::new(15, 0, 0),
-            semver::Version::new(16, 0, 0),
+            semver::Version::new(17, 0, 0),
         ),
+        ..Default::default()
     };
 
     let global_connection = Postgres::new(global_connection_config)?;
     let _ = CortexPostgres::new(global_connection, cortex_conf.clone())
+        .add_observer(|event: &CortexEvent| {
+            if let CortexEvent::StepCommitted {
+                step,
+                index,
+                steps,
+                duration,
+            } = event
+            {
+                println!("{}/{} {} took {:?}", index + 1, steps, step.name, duration);
+            }
+        })
         .add_step(global_db_step)
         .execute()?;
 
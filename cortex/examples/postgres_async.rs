@@ -0,0 +1,46 @@
+use cortex::{connection::ExecuteType, prelude::*};
+
+#[cfg(all(feature = "postgres", feature = "async"))]
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let users = Table::new("users")
+        .add_prop(("id", PropType::Int, None))
+        .add_prop(("name", PropType::Text, None));
+    let init = Step::new(
+        "Init Schema",
+        StepType::Update,
+        semver::Version::new(0, 0, 1),
+    )
+    .add_statement(&users, DbAction::Create);
+    let backfill = Step::new(
+        "Backfill Names",
+        StepType::Update,
+        semver::Version::new(0, 0, 2),
+    )
+    .add_statement(
+        Code::new("backfill_names").on_postgres_async(|db| {
+            Box::pin(async move {
+                db.execute(ExecuteType::Command(
+                    "UPDATE users SET name = 'unknown' WHERE name IS NULL".into(),
+                ))
+                .await?;
+                Ok(())
+            })
+        }),
+        DbAction::Create,
+    )
+    // code is opaque to the safety checks
+    .allow_destructive();
+    let connection = AsyncPostgres::new(ConnectionConfig::<Postgres>::default()).await?;
+    let cortex_conf = CortexPostgresConfig {
+        execution_mode: ExecutionMode::AllOrNothing,
+        ..Default::default()
+    };
+    CortexPostgres::new_async(connection, cortex_conf)
+        .await
+        .add_step(init)
+        .add_step(backfill)
+        .execute()
+        .await?;
+    Ok(())
+}
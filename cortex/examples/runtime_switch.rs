@@ -24,13 +24,15 @@ fn main() {
     )
     .add_statement(&users, DbAction::Create)
     .add_statement(&orders, DbAction::Create)
-    .add_statement(&users, DbAction::Drop);
+    .add_statement(&users, DbAction::Drop)
+    .allow_destructive();
     let cleanup = Step::new(
         "Update Schema",
         StepType::Update,
         semver::Version::new(0, 0, 3),
     )
-    .add_statement(&db, DbAction::Drop);
+    .add_statement(&db, DbAction::Drop)
+    .allow_destructive();
 
     match db_in_use {
         Db::Postgres => {
@@ -40,8 +42,9 @@ fn main() {
                 execution_mode: ExecutionMode::Optimistic,
                 supported_db_versions: (
                     semver::Version::new(15, 0, 0),
-                    semver::Version::new(16, 0, 0),
+                    semver::Version::new(17, 0, 0),
                 ),
+                ..Default::default()
             };
             let connection = Postgres::new(client_conf).expect("to connect to db");
             let cortex = CortexPostgres::new(connection, cortex_conf)
@@ -1,4 +1,7 @@
-use crate::objects::statement::{DbAction, Statement};
+use crate::objects::{
+    insert::Value,
+    statement::{DbAction, ExistencePolicy, Statement},
+};
 use thiserror::Error;
 
 #[cfg(feature = "mongodb")]
@@ -38,7 +41,108 @@ impl<'a, T> ConnectionConfig<'a, T> {
 
 pub enum ExecuteType {
     Command(String),
-    Driver(Statement, DbAction),
+    /// A single query with bind parameters for its placeholders
+    Prepared(String, Vec<Value>),
+    /// A statement the driver maps itself, honouring the existence policy
+    Driver(Statement, DbAction, ExistencePolicy),
+}
+
+/// masks string literals and dollar quoted bodies so credentials and values are not logged
+/// like the password of CREATE ROLE or the values of an INSERT
+pub(crate) fn redact_sql(query: &str) -> String {
+    let bytes = query.as_bytes();
+    let mut redacted = String::with_capacity(query.len());
+    let mut copied = 0;
+    let mut index = 0;
+    while index < bytes.len() {
+        let (start, end) = match bytes[index] {
+            // quotes inside of identifiers and comments do not start a literal
+            b'"' => {
+                index = skip_past(query, index + 1, "\"");
+                continue;
+            }
+            b'-' if query[index..].starts_with("--") => {
+                index = skip_past(query, index + 2, "\n");
+                continue;
+            }
+            b'\'' => {
+                // E'' strings escape a quote with a backslash as well
+                let escapes = index > 0
+                    && bytes[index - 1].eq_ignore_ascii_case(&b'e')
+                    && (index < 2 || !is_identifier(bytes[index - 2]));
+                (index, literal_end(bytes, index + 1, escapes))
+            }
+            b'$' => match dollar_tag(query, index) {
+                Some(tag) => (index, skip_past(query, index + tag.len(), tag)),
+                None => {
+                    index += 1;
+                    continue;
+                }
+            },
+            _ => {
+                index += 1;
+                continue;
+            }
+        };
+        redacted.push_str(&query[copied..start]);
+        match dollar_tag(query, start) {
+            Some(tag) => {
+                redacted.push_str(tag);
+                redacted.push_str("***");
+                redacted.push_str(tag);
+            }
+            None => redacted.push_str("'***'"),
+        }
+        copied = end;
+        index = end;
+    }
+    redacted.push_str(&query[copied..]);
+    redacted
+}
+
+fn is_identifier(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+/// the index after the next delimiter, the end of the query if there is none
+fn skip_past(query: &str, from: usize, delimiter: &str) -> usize {
+    query[from..]
+        .find(delimiter)
+        .map_or(query.len(), |found| from + found + delimiter.len())
+}
+
+/// the index after the closing quote of a literal, doubled quotes are escaped quotes
+fn literal_end(bytes: &[u8], from: usize, escapes: bool) -> usize {
+    let mut index = from;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'\\' if escapes => index += 2,
+            b'\'' if bytes.get(index + 1) == Some(&b'\'') => index += 2,
+            b'\'' => return index + 1,
+            _ => index += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// the tag opening a dollar quoted string at the index, like $$ or $body$
+/// placeholders like $1 are not tags
+fn dollar_tag(query: &str, index: usize) -> Option<&str> {
+    let bytes = query.as_bytes();
+    if bytes[index] != b'$' || (index > 0 && is_identifier(bytes[index - 1])) {
+        return None;
+    }
+    let name = bytes[index + 1..]
+        .iter()
+        .take_while(|byte| is_identifier(**byte))
+        .count();
+    let closing = index + 1 + name;
+    match bytes.get(closing) {
+        Some(b'$') if !bytes[index + 1].is_ascii_digit() || name == 0 => {
+            Some(&query[index..=closing])
+        }
+        _ => None,
+    }
 }
 
 #[derive(Error, Debug)]
@@ -53,6 +157,16 @@ pub enum ConnectionError {
     CommitError(#[from] CommitError),
     #[error("Database query error: {0}")]
     QueryError(#[from] QueryError),
+    #[error("Database transient error: {0}")]
+    TransientError(#[from] TransientError),
+}
+
+impl ConnectionError {
+    /// Whether the failure can go away when the work is run again
+    /// like serialization failures, lock timeouts or connection resets
+    pub fn is_transient(&self) -> bool {
+        matches!(self, ConnectionError::TransientError(_))
+    }
 }
 
 #[derive(Error, Debug)]
@@ -63,6 +177,10 @@ pub struct ConnectError(pub String);
 #[error("Query execution failed:\n  Query: {0}\n  Error: {1}")]
 pub struct ExecuteError(pub String, pub String);
 
+#[derive(Error, Debug)]
+#[error("Query failed for now and can be retried:\n  Query: {0}\n  Error: {1}")]
+pub struct TransientError(pub String, pub String);
+
 #[derive(Error, Debug)]
 #[error("failed to execute query {0}")]
 pub struct QueryError(pub String);
@@ -74,3 +192,44 @@ pub struct TransactionError(pub String);
 #[derive(Error, Debug)]
 #[error("failed to commit {0}")]
 pub struct CommitError(pub String);
+
+#[cfg(test)]
+mod tests {
+    use super::redact_sql;
+
+    #[test]
+    fn redacts_passwords() {
+        assert_eq!(
+            redact_sql("CREATE ROLE app WITH LOGIN PASSWORD 'hunter2';"),
+            "CREATE ROLE app WITH LOGIN PASSWORD '***';"
+        );
+    }
+
+    #[test]
+    fn redacts_literals_with_escaped_quotes() {
+        assert_eq!(
+            redact_sql("INSERT INTO users (name, note, sign) VALUES ('O''Brien', E'it\\'s', '$');"),
+            "INSERT INTO users (name, note, sign) VALUES ('***', E'***', '***');"
+        );
+    }
+
+    #[test]
+    fn redacts_dollar_quoted_bodies() {
+        assert_eq!(
+            redact_sql("DO $$ BEGIN PERFORM 'secret'; END $$;"),
+            "DO $$***$$;"
+        );
+        assert_eq!(
+            redact_sql(
+                "CREATE FUNCTION f() RETURNS text AS $body$ SELECT 'x' $body$ LANGUAGE sql;"
+            ),
+            "CREATE FUNCTION f() RETURNS text AS $body$***$body$ LANGUAGE sql;"
+        );
+    }
+
+    #[test]
+    fn keeps_statements_without_literals() {
+        let query = "SELECT \"it's\" FROM __version__ WHERE version = $1 -- don't";
+        assert_eq!(redact_sql(query), query);
+    }
+}
@@ -1,12 +1,18 @@
+use std::time::Duration;
+
 use mongodb::{
+    bson::doc,
+    error::{ErrorKind, TRANSIENT_TRANSACTION_ERROR},
     options::{ClientOptions, ServerApi, ServerApiVersion},
     Client, ClientSession,
 };
 
 use crate::{
-    connection::{ExecuteError, ExecuteType},
-    db::{connection::ConnectionConfig, producer::mongodb::MongodbStatementProducer},
-    objects::statement::Statement,
+    connection::{ConnectionError, ExecuteError, ExecuteType, TransientError},
+    db::{
+        connection::ConnectionConfig,
+        producer::mongodb::{MongodbStatementProducer, Precondition},
+    },
 };
 
 impl ConnectionConfig<'_, Mongo> {
@@ -37,34 +43,54 @@ impl Default for ConnectionConfig<'_, Mongo> {
 pub struct Mongo(pub Client);
 
 impl Mongo {
+    /// execute the commands of a statement
+    /// every command is limited to the max time if one is given
     pub async fn execute(
         &mut self,
         data: ExecuteType,
-        session: Option<&mut ClientSession>,
-    ) -> Result<(), ExecuteError> {
+        mut session: Option<&mut ClientSession>,
+        max_time: Option<Duration>,
+    ) -> Result<(), ConnectionError> {
         match data {
-            ExecuteType::Command(_) => {
+            ExecuteType::Command(_) | ExecuteType::Prepared(_, _) => {
                 panic!("mongodb does not work like sql we can not execute command directly afaik")
             }
-            ExecuteType::Driver(statement, action) => match statement {
-                Statement::Table(t) => {
-                    MongodbStatementProducer::collection_statement((&self.0, session), &t, &action)
-                        .await
-                }
-                Statement::Database(_) => {
-                    // MongodbStatementProducer::database_statement(&self.0, &d, &action)
-                    // do nothing
-                    Ok(())
+            ExecuteType::Driver(statement, action, policy) => {
+                for command in MongodbStatementProducer::map(&statement, &action, &policy)? {
+                    let db = self.0.database(&command.database);
+                    if let Some(precondition) = &command.precondition {
+                        let name = match precondition {
+                            Precondition::CollectionExists(name)
+                            | Precondition::CollectionMissing(name) => name,
+                        };
+                        // listCollections is not allowed in a transaction, the check runs outside of it
+                        let names = db
+                            .list_collection_names(doc! { "name": name.to_string() })
+                            .await
+                            .map_err(|e| execute_error(command.redacted(), e))?;
+                        let exists = !names.is_empty();
+                        if exists != matches!(precondition, Precondition::CollectionExists(_)) {
+                            continue;
+                        }
+                    }
+                    let mut document = command.command.clone();
+                    if let Some(max_time) = max_time {
+                        document.insert("maxTimeMS", max_time.as_millis() as i64);
+                    }
+                    let result = match session.as_deref_mut() {
+                        Some(session) => db.run_command_with_session(document, None, session).await,
+                        None => db.run_command(document, None).await,
+                    };
+                    result.map_err(|e| execute_error(command.redacted(), e))?;
                 }
-            },
+                Ok(())
+            }
         }
     }
 
     #[cfg(feature = "async")]
     pub async fn new(config: ConnectionConfig<'_, Mongo>) -> mongodb::error::Result<Self> {
         // Replace the placeholder with your Atlas connection string
-
-        use mongodb::bson::doc;
         let uri = config.get_uri();
         let mut client_options = ClientOptions::parse(uri).await?;
 
@@ -80,7 +106,7 @@ impl Mongo {
             .database("admin")
             .run_command(doc! {"ping": 1}, None)
             .await?;
-        println!("Pinged your deployment. You successfully connected to MongoDB!");
+        tracing::info!(database = config.database, "connected to mongodb");
 
         Ok(Self(client))
     }
@@ -95,3 +121,15 @@ impl Mongo {
         self.0.database(database_name)
     }
 }
+
+/// transient transaction errors and lost connections can succeed when they are run again
+pub(crate) fn is_transient(error: &mongodb::error::Error) -> bool {
+    error.contains_label(TRANSIENT_TRANSACTION_ERROR) || matches!(*error.kind, ErrorKind::Io(_))
+}
+
+fn execute_error(command: String, error: mongodb::error::Error) -> ConnectionError {
+    match is_transient(&error) {
+        true => TransientError(command, error.to_string()).into(),
+        false => ExecuteError(command, error.to_string()).into(),
+    }
+}
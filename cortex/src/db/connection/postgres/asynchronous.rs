@@ -0,0 +1,290 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, PoisonError},
+};
+
+use postgres::Row;
+use postgres_types::ToSql;
+use tokio::sync::{Mutex, MutexGuard};
+use tokio_postgres::{Client, NoTls};
+
+use super::{bind_params, commit_error, execute_error, Postgres};
+use crate::{
+    connection::{
+        redact_sql, ConnectError, ConnectionError, ExecuteType, QueryError, TransactionError,
+    },
+    db::connection::ConnectionConfig,
+};
+
+/// Future returned by the methods of an [`AsyncPostgresExecutor`]
+pub type AsyncPostgresFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// Client shared by all clones of an async connection
+pub struct AsyncPostgresClient {
+    client: Client,
+    /// uri a lost client reconnects to
+    uri: String,
+    /// a transaction was dropped without a commit
+    /// it is rolled back before the next command is sent
+    rollback_pending: bool,
+}
+
+#[derive(Clone)]
+/// Async postgres connection built on `tokio-postgres`
+/// the connection task is spawned on the tokio runtime it is created in
+pub struct AsyncPostgres(
+    pub Arc<Mutex<AsyncPostgresClient>>,
+    /// commands deferred by guards dropped without the client, sent before the next command
+    Arc<std::sync::Mutex<Vec<String>>>,
+);
+
+/// Transaction on the shared client of an async connection
+/// rolled back before the next command when it is dropped without a commit
+pub struct AsyncPostgresTransaction<'a> {
+    client: MutexGuard<'a, AsyncPostgresClient>,
+    committed: bool,
+}
+
+impl AsyncPostgresClient {
+    /// rolls back the transaction dropped without a commit
+    async fn settle(&mut self) -> Result<(), tokio_postgres::Error> {
+        if self.rollback_pending {
+            self.client.batch_execute("ROLLBACK").await?;
+            self.rollback_pending = false;
+        }
+        Ok(())
+    }
+
+    async fn execute(&self, data: ExecuteType) -> Result<(), ConnectionError> {
+        match data {
+            ExecuteType::Command(command) => {
+                tracing::debug!(command = %redact_sql(&command), "executing command");
+                self.client
+                    .batch_execute(command.as_str())
+                    .await
+                    .map_err(|e| execute_error(&command, e))
+            }
+            ExecuteType::Prepared(query, values) => {
+                tracing::debug!(
+                    query = %redact_sql(&query),
+                    parameters = values.len(),
+                    "executing query"
+                );
+                self.client
+                    .execute(query.as_str(), &bind_params(&values))
+                    .await
+                    .map(|_| ())
+                    .map_err(|e| execute_error(&query, e))
+            }
+            ExecuteType::Driver(..) => panic!("c driver based execution not supported"),
+        }
+    }
+
+    async fn query(
+        &self,
+        data: ExecuteType,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, QueryError> {
+        match data {
+            ExecuteType::Command(command) => self
+                .client
+                .query(command.as_str(), params)
+                .await
+                .map_err(|e| QueryError(e.to_string())),
+            ExecuteType::Prepared(query, values) => self
+                .client
+                .query(query.as_str(), &bind_params(&values))
+                .await
+                .map_err(|e| QueryError(e.to_string())),
+            ExecuteType::Driver(..) => panic!("c driver based execution not supported"),
+        }
+    }
+}
+
+impl AsyncPostgres {
+    /// create a new connection
+    pub async fn new(config: ConnectionConfig<'_, Postgres>) -> Result<Self, ConnectError> {
+        let uri = config.get_uri();
+        let client = connect(&uri)
+            .await
+            .map_err(|e| ConnectError(format!("{:#?}\non db {:#?}", e, config.database)))?;
+        Ok(Self(
+            Arc::new(Mutex::new(AsyncPostgresClient {
+                client,
+                uri,
+                rollback_pending: false,
+            })),
+            Arc::default(),
+        ))
+    }
+
+    /// sends the command before the next command on the connection
+    /// for cleanups in drop, which can not wait for the client
+    pub fn defer(&self, command: String) {
+        self.1
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(command);
+    }
+
+    /// whether the connection to the server was lost
+    pub async fn is_closed(&self) -> bool {
+        self.0.lock().await.client.is_closed()
+    }
+
+    /// replaces the lost client of all clones of the connection with a new one
+    /// session state like advisory locks and settings does not survive it
+    pub async fn reconnect(&self) -> Result<(), ConnectError> {
+        let mut shared = self.0.lock().await;
+        shared.client = connect(&shared.uri)
+            .await
+            .map_err(|e| ConnectError(format!("{:#?}", e)))?;
+        // the transaction died with the old session
+        shared.rollback_pending = false;
+        Ok(())
+    }
+
+    /// the shared client once the last dropped transaction is rolled back
+    /// and the deferred commands are sent
+    async fn client(&self) -> Result<MutexGuard<'_, AsyncPostgresClient>, tokio_postgres::Error> {
+        let mut client = self.0.lock().await;
+        client.settle().await?;
+        let deferred = std::mem::take(&mut *self.1.lock().unwrap_or_else(PoisonError::into_inner));
+        for command in deferred {
+            client.client.batch_execute(&command).await?;
+        }
+        Ok(client)
+    }
+
+    /// starts a transaction on the client shared by all clones of the connection
+    /// other commands on the connection wait until the transaction is finished
+    pub async fn create_transaction(
+        &self,
+    ) -> Result<AsyncPostgresTransaction<'_>, TransactionError> {
+        let client = self
+            .client()
+            .await
+            .map_err(|e| TransactionError(format!("failed to create transaction: {}", e)))?;
+        client
+            .client
+            .batch_execute("BEGIN")
+            .await
+            .map_err(|e| TransactionError(format!("failed to create transaction: {}", e)))?;
+        Ok(AsyncPostgresTransaction {
+            client,
+            committed: false,
+        })
+    }
+
+    /// execute a command
+    pub async fn execute(&self, data: ExecuteType) -> Result<(), ConnectionError> {
+        let client = self.client().await.map_err(|e| {
+            TransactionError(format!("failed to roll back dropped transaction: {}", e))
+        })?;
+        client.execute(data).await
+    }
+
+    pub async fn query(
+        &self,
+        data: ExecuteType,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, QueryError> {
+        let client = self
+            .client()
+            .await
+            .map_err(|e| QueryError(format!("failed to roll back dropped transaction: {}", e)))?;
+        client.query(data, params).await
+    }
+}
+
+/// connects a client and spawns its connection task on the current runtime
+async fn connect(uri: &str) -> Result<Client, tokio_postgres::Error> {
+    let (client, connection) = tokio_postgres::connect(uri, NoTls).await?;
+    tokio::spawn(async move {
+        if let Err(error) = connection.await {
+            tracing::error!(error = %error, "postgres connection closed");
+        }
+    });
+    Ok(client)
+}
+
+impl AsyncPostgresTransaction<'_> {
+    pub async fn execute(&mut self, data: ExecuteType) -> Result<(), ConnectionError> {
+        self.client.execute(data).await
+    }
+
+    pub async fn query(
+        &mut self,
+        data: ExecuteType,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, QueryError> {
+        self.client.query(data, params).await
+    }
+
+    pub async fn commit(mut self) -> Result<(), ConnectionError> {
+        self.committed = true;
+        self.client
+            .client
+            .batch_execute("COMMIT")
+            .await
+            .map_err(commit_error)
+    }
+}
+
+impl Drop for AsyncPostgresTransaction<'_> {
+    fn drop(&mut self) {
+        // the rollback can not be awaited here, the next user of the client sends it
+        if !self.committed {
+            self.client.rollback_pending = true;
+        }
+    }
+}
+
+/// Common interface of an async connection and an open transaction
+/// code statements receive it to work on the database
+pub trait AsyncPostgresExecutor {
+    fn execute(
+        &mut self,
+        data: ExecuteType,
+    ) -> AsyncPostgresFuture<'_, Result<(), ConnectionError>>;
+    fn query<'a>(
+        &'a mut self,
+        data: ExecuteType,
+        params: &'a [&'a (dyn ToSql + Sync)],
+    ) -> AsyncPostgresFuture<'a, Result<Vec<Row>, QueryError>>;
+}
+
+impl AsyncPostgresExecutor for AsyncPostgres {
+    fn execute(
+        &mut self,
+        data: ExecuteType,
+    ) -> AsyncPostgresFuture<'_, Result<(), ConnectionError>> {
+        Box::pin(AsyncPostgres::execute(self, data))
+    }
+
+    fn query<'a>(
+        &'a mut self,
+        data: ExecuteType,
+        params: &'a [&'a (dyn ToSql + Sync)],
+    ) -> AsyncPostgresFuture<'a, Result<Vec<Row>, QueryError>> {
+        Box::pin(AsyncPostgres::query(self, data, params))
+    }
+}
+
+impl AsyncPostgresExecutor for AsyncPostgresTransaction<'_> {
+    fn execute(
+        &mut self,
+        data: ExecuteType,
+    ) -> AsyncPostgresFuture<'_, Result<(), ConnectionError>> {
+        Box::pin(AsyncPostgresTransaction::execute(self, data))
+    }
+
+    fn query<'a>(
+        &'a mut self,
+        data: ExecuteType,
+        params: &'a [&'a (dyn ToSql + Sync)],
+    ) -> AsyncPostgresFuture<'a, Result<Vec<Row>, QueryError>> {
+        Box::pin(AsyncPostgresTransaction::query(self, data, params))
+    }
+}
@@ -1,16 +1,28 @@
 use std::{
     cell::{RefCell, RefMut},
+    error::Error,
     rc::Rc,
 };
 
-use postgres::{Client, Row};
-use postgres_types::ToSql;
+use bytes::BytesMut;
+use postgres::{error::SqlState, Client, Row};
+use postgres_types::{IsNull, ToSql, Type};
 
 use crate::{
     connection::{
-        CommitError, ConnectError, ExecuteError, ExecuteType, QueryError, TransactionError,
+        redact_sql, CommitError, ConnectError, ConnectionError, ExecuteError, ExecuteType,
+        QueryError, TransactionError, TransientError,
     },
     db::connection::ConnectionConfig,
+    objects::insert::Value,
+};
+
+#[cfg(feature = "async")]
+mod asynchronous;
+#[cfg(feature = "async")]
+pub use asynchronous::{
+    AsyncPostgres, AsyncPostgresClient, AsyncPostgresExecutor, AsyncPostgresFuture,
+    AsyncPostgresTransaction,
 };
 
 impl ConnectionConfig<'_, Postgres> {
@@ -39,27 +51,73 @@ impl Default for ConnectionConfig<'_, Postgres> {
 #[derive(Clone)]
 /// Postgres connection
 /// not thread safe only used to create the db layout with cortex
-pub struct Postgres(pub Rc<RefCell<Client>>);
+pub struct Postgres(
+    pub Rc<RefCell<Client>>,
+    /// uri a lost client reconnects to
+    Rc<str>,
+);
 
-pub struct PostgresTransaction<'a>(pub postgres::Transaction<'a>);
+/// Transaction on the shared client of a connection
+/// rolled back when it is dropped without a commit
+pub struct PostgresTransaction<'a> {
+    client: RefMut<'a, Client>,
+    committed: bool,
+}
 
 impl<'a> PostgresTransaction<'a> {
-    pub fn execute(&mut self, data: ExecuteType) -> Result<(), ExecuteError> {
+    pub fn execute(&mut self, data: ExecuteType) -> Result<(), ConnectionError> {
         match data {
             ExecuteType::Command(command) => {
-                println!("executing command: {}", command);
-                return self
-                    .0
+                tracing::debug!(command = %redact_sql(&command), "executing command");
+                self.client
                     .batch_execute(command.as_str())
-                    .map_err(|e| ExecuteError(command, e.to_string()));
+                    .map_err(|e| execute_error(&command, e))
             }
-            ExecuteType::Driver(_, _) => panic!("c driver based execution not supported"),
+            ExecuteType::Prepared(query, values) => {
+                tracing::debug!(
+                    query = %redact_sql(&query),
+                    parameters = values.len(),
+                    "executing query"
+                );
+                self.client
+                    .execute(query.as_str(), &bind_params(&values))
+                    .map(|_| ())
+                    .map_err(|e| execute_error(&query, e))
+            }
+            ExecuteType::Driver(..) => panic!("c driver based execution not supported"),
         }
     }
 
-    pub fn commit(self) -> Result<(), CommitError> {
-        // Check if this is the only reference to the transaction
-        self.0.commit().map_err(|e| CommitError(e.to_string()))
+    pub fn query(
+        &mut self,
+        data: ExecuteType,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, QueryError> {
+        match data {
+            ExecuteType::Command(command) => self
+                .client
+                .query(command.as_str(), params)
+                .map_err(|e| QueryError(e.to_string())),
+            ExecuteType::Prepared(query, values) => self
+                .client
+                .query(query.as_str(), &bind_params(&values))
+                .map_err(|e| QueryError(e.to_string())),
+            ExecuteType::Driver(..) => panic!("c driver based execution not supported"),
+        }
+    }
+
+    pub fn commit(mut self) -> Result<(), ConnectionError> {
+        self.committed = true;
+        self.client.batch_execute("COMMIT").map_err(commit_error)
+    }
+}
+
+impl Drop for PostgresTransaction<'_> {
+    fn drop(&mut self) {
+        if !self.committed {
+            // the connection is unusable if the rollback fails, which surfaces on the next command
+            let _ = self.client.batch_execute("ROLLBACK");
+        }
     }
 }
 
@@ -71,34 +129,65 @@ impl Postgres {
         let client = Client::connect(&uri, postgres::NoTls)
             .map_err(|e| ConnectError(format!("{:#?}\non db {:#?}", e, config.database)))?;
 
-        Ok(Self(Rc::new(RefCell::new(client))))
+        Ok(Self(Rc::new(RefCell::new(client)), Rc::from(uri)))
+    }
+
+    /// whether the connection to the server was lost
+    pub fn is_closed(&self) -> bool {
+        self.0.borrow().is_closed()
+    }
+
+    /// replaces the lost client of all clones of the connection with a new one
+    /// session state like advisory locks and settings does not survive it
+    pub fn reconnect(&self) -> Result<(), ConnectError> {
+        let client = Client::connect(&self.1, postgres::NoTls)
+            .map_err(|e| ConnectError(format!("{:#?}", e)))?;
+        *self.0.borrow_mut() = client;
+        Ok(())
     }
 
-    pub fn get_client(&mut self) -> RefMut<Client> {
+    pub fn get_client(&mut self) -> RefMut<'_, Client> {
         self.0.borrow_mut()
     }
 
-    pub fn create_transaction(&mut self) -> Result<PostgresTransaction, TransactionError> {
-        let client = Rc::get_mut(&mut self.0).unwrap();
-        let transaction = client
-            .get_mut()
-            .transaction()
+    /// starts a transaction on the client shared by all clones of the connection
+    pub fn create_transaction(&mut self) -> Result<PostgresTransaction<'_>, TransactionError> {
+        let mut client = self
+            .0
+            .try_borrow_mut()
+            .map_err(|e| TransactionError(format!("failed to create transaction: {}", e)))?;
+        client
+            .batch_execute("BEGIN")
             .map_err(|e| TransactionError(format!("failed to create transaction: {}", e)))?;
-        Ok(PostgresTransaction(transaction))
+        Ok(PostgresTransaction {
+            client,
+            committed: false,
+        })
     }
 
     /// execute a command
-    pub fn execute(&mut self, data: ExecuteType) -> Result<(), ExecuteError> {
+    pub fn execute(&mut self, data: ExecuteType) -> Result<(), ConnectionError> {
         match data {
             ExecuteType::Command(command) => {
-                println!("executing command: {}", command);
-                return self
-                    .0
+                tracing::debug!(command = %redact_sql(&command), "executing command");
+                self.0
                     .borrow_mut()
                     .batch_execute(command.as_str())
-                    .map_err(|e| ExecuteError(command, e.to_string()));
+                    .map_err(|e| execute_error(&command, e))
             }
-            ExecuteType::Driver(_, _) => panic!("c driver based execution not supported"),
+            ExecuteType::Prepared(query, values) => {
+                tracing::debug!(
+                    query = %redact_sql(&query),
+                    parameters = values.len(),
+                    "executing query"
+                );
+                self.0
+                    .borrow_mut()
+                    .execute(query.as_str(), &bind_params(&values))
+                    .map(|_| ())
+                    .map_err(|e| execute_error(&query, e))
+            }
+            ExecuteType::Driver(..) => panic!("c driver based execution not supported"),
         }
     }
 
@@ -113,7 +202,130 @@ impl Postgres {
                 .borrow_mut()
                 .query(command.as_str(), params)
                 .map_err(|e| QueryError(e.to_string())),
-            ExecuteType::Driver(_, _) => panic!("c driver based execution not supported"),
+            ExecuteType::Prepared(query, values) => self
+                .0
+                .borrow_mut()
+                .query(query.as_str(), &bind_params(&values))
+                .map_err(|e| QueryError(e.to_string())),
+            ExecuteType::Driver(..) => panic!("c driver based execution not supported"),
+        }
+    }
+}
+
+/// Common interface of a connection and an open transaction
+/// code statements receive it to work on the database
+pub trait PostgresExecutor {
+    fn execute(&mut self, data: ExecuteType) -> Result<(), ConnectionError>;
+    fn query(
+        &mut self,
+        data: ExecuteType,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, QueryError>;
+}
+
+impl PostgresExecutor for Postgres {
+    fn execute(&mut self, data: ExecuteType) -> Result<(), ConnectionError> {
+        Postgres::execute(self, data)
+    }
+
+    fn query(
+        &mut self,
+        data: ExecuteType,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, QueryError> {
+        Postgres::query(self, data, params)
+    }
+}
+
+impl PostgresExecutor for PostgresTransaction<'_> {
+    fn execute(&mut self, data: ExecuteType) -> Result<(), ConnectionError> {
+        PostgresTransaction::execute(self, data)
+    }
+
+    fn query(
+        &mut self,
+        data: ExecuteType,
+        params: &[&(dyn ToSql + Sync)],
+    ) -> Result<Vec<Row>, QueryError> {
+        PostgresTransaction::query(self, data, params)
+    }
+}
+
+/// serialization failures, deadlocks, lock timeouts and lost connections can succeed
+/// when they are run again, the orchestrators reconnect before they retry
+fn is_transient(error: &postgres::Error) -> bool {
+    is_lost(error)
+        || error.code().is_some_and(|code| {
+            *code == SqlState::T_R_SERIALIZATION_FAILURE
+                || *code == SqlState::T_R_DEADLOCK_DETECTED
+                || *code == SqlState::LOCK_NOT_AVAILABLE
+        })
+}
+
+/// the connection was closed or reset while the command was sent
+fn is_lost(error: &postgres::Error) -> bool {
+    error.is_closed()
+        || error
+            .source()
+            .is_some_and(|source| source.is::<std::io::Error>())
+}
+
+/// a commit on a lost connection may have been applied, it is never retried
+fn commit_error(error: postgres::Error) -> ConnectionError {
+    match is_transient(&error) && !is_lost(&error) {
+        true => TransientError("COMMIT".to_string(), error.to_string()).into(),
+        false => CommitError(error.to_string()).into(),
+    }
+}
+
+/// the command is redacted, errors end up in logs
+fn execute_error(command: &str, error: postgres::Error) -> ConnectionError {
+    match is_transient(&error) {
+        true => TransientError(redact_sql(command), error.to_string()).into(),
+        false => ExecuteError(redact_sql(command), error.to_string()).into(),
+    }
+}
+
+fn bind_params(values: &[Value]) -> Vec<&(dyn ToSql + Sync)> {
+    values.iter().map(|v| v as &(dyn ToSql + Sync)).collect()
+}
+
+/// values are bound by the driver of the wrapped type
+/// null is accepted for every column type
+impl ToSql for Value {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        match self {
+            Value::Int(v) => v.to_sql(ty, out),
+            Value::SmallInt(v) => v.to_sql(ty, out),
+            Value::BigInt(v) => v.to_sql(ty, out),
+            Value::Double(v) => v.to_sql(ty, out),
+            Value::Text(v) | Value::Date(v) | Value::Timestamp(v) => v.to_sql(ty, out),
+            Value::Bool(v) => v.to_sql(ty, out),
+            Value::Null => Ok(IsNull::Yes),
+        }
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    fn to_sql_checked(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn std::error::Error + Sync + Send>> {
+        match self {
+            Value::Int(v) => v.to_sql_checked(ty, out),
+            Value::SmallInt(v) => v.to_sql_checked(ty, out),
+            Value::BigInt(v) => v.to_sql_checked(ty, out),
+            Value::Double(v) => v.to_sql_checked(ty, out),
+            Value::Text(v) | Value::Date(v) | Value::Timestamp(v) => v.to_sql_checked(ty, out),
+            Value::Bool(v) => v.to_sql_checked(ty, out),
+            Value::Null => Ok(IsNull::Yes),
         }
     }
 }
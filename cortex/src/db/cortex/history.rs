@@ -0,0 +1,66 @@
+use std::time::{Duration, SystemTime};
+
+use crate::{db::cortex::ExecutionMode, objects::step::StepType};
+
+/// version of cortex recorded with every applied step
+pub(crate) const CORTEX_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, Clone)]
+/// A step run against the database as it is recorded in `__version__`
+/// records written by older versions of cortex only carry the version
+pub struct HistoryRecord {
+    /// version of the step
+    pub version: semver::Version,
+    /// name of the step
+    pub name: Option<String>,
+    /// type of the step
+    pub step_type: Option<StepType>,
+    /// checksum of the statements the step ran
+    pub checksum: Option<String>,
+    /// when the step finished
+    pub applied_at: SystemTime,
+    /// how long the step took
+    pub duration: Option<Duration>,
+    /// execution mode the step ran with, steps without a transaction are optimistic
+    pub execution_mode: Option<ExecutionMode>,
+    /// whether the step was applied or failed
+    pub success: bool,
+    /// version of cortex which ran the step
+    pub cortex_version: Option<String>,
+}
+
+/// name a step type is stored with
+pub(crate) fn step_type_name(step_type: &StepType) -> &'static str {
+    match step_type {
+        StepType::InitSetup => "init_setup",
+        StepType::Update => "update",
+    }
+}
+
+/// parses a stored step type name
+pub(crate) fn parse_step_type(name: &str) -> Option<StepType> {
+    match name {
+        "init_setup" => Some(StepType::InitSetup),
+        "update" => Some(StepType::Update),
+        _ => None,
+    }
+}
+
+/// name an execution mode is stored with
+pub(crate) fn execution_mode_name(mode: &ExecutionMode) -> &'static str {
+    match mode {
+        ExecutionMode::Optimistic => "optimistic",
+        ExecutionMode::Transactional => "transactional",
+        ExecutionMode::AllOrNothing => "all_or_nothing",
+    }
+}
+
+/// parses a stored execution mode name
+pub(crate) fn parse_execution_mode(name: &str) -> Option<ExecutionMode> {
+    match name {
+        "optimistic" => Some(ExecutionMode::Optimistic),
+        "transactional" => Some(ExecutionMode::Transactional),
+        "all_or_nothing" => Some(ExecutionMode::AllOrNothing),
+        _ => None,
+    }
+}
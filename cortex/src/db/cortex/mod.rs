@@ -1,22 +1,83 @@
+use std::time::Duration;
+
 use thiserror::Error;
 
 use crate::connection::ConnectionError;
 
+pub mod history;
 pub mod mongodb;
+pub mod observer;
+pub mod plan;
 pub mod postgres;
 
 pub mod prelude {
+    pub use super::history::HistoryRecord;
     #[cfg(feature = "mongodb")]
     pub use super::mongodb::*;
+    pub use super::observer::{CortexEvent, CortexObserver};
+    pub use super::plan::{MigrationPlan, PlannedStatement, PlannedStep};
     #[cfg(feature = "postgres")]
     pub use super::postgres::*;
-    pub use super::ExecutionMode;
+    pub use super::{
+        ChecksumMismatchError, CortexError, DestructiveChangeError, ExecutionMode,
+        ExecutionModeError, LockError, RetryPolicy, SchemaVersionError, StepValidationError,
+        UnsupportedDbVersionError,
+    };
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default)]
 pub enum ExecutionMode {
     Optimistic,
+    #[default]
     Transactional,
+    /// all pending steps and their history run in a single transaction
+    /// a failure in any step rolls back the whole run, only supported by postgres
+    AllOrNothing,
+}
+
+#[derive(Clone, Debug)]
+/// How often a step that failed with a transient error is run again
+/// only steps running in a transaction are retried, others may be applied partially
+pub struct RetryPolicy {
+    /// runs of a step including the first one, 1 disables retries
+    pub attempts: u32,
+    /// wait before the first retry, doubled for every further retry
+    pub backoff: Duration,
+    /// the longest wait between two runs
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 1,
+            backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The wait before the given retry, starting at 1
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    /// use cortex::prelude::*;
+    /// let policy = RetryPolicy {
+    ///     attempts: 5,
+    ///     backoff: Duration::from_millis(100),
+    ///     max_backoff: Duration::from_millis(300),
+    /// };
+    /// assert_eq!(policy.delay(1), Duration::from_millis(100));
+    /// assert_eq!(policy.delay(2), Duration::from_millis(200));
+    /// assert_eq!(policy.delay(3), Duration::from_millis(300));
+    /// ```
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
 }
 
 #[derive(Error, Debug)]
@@ -27,6 +88,23 @@ pub enum CortexError {
     StepValidation(#[from] StepValidationError),
     #[error("Cortex schema error: {0}")]
     SchemaVersion(#[from] SchemaVersionError),
+    #[error("Cortex destructive change: {0}")]
+    DestructiveChange(#[from] DestructiveChangeError),
+    #[error("Cortex checksum error: {0}")]
+    ChecksumMismatch(#[from] ChecksumMismatchError),
+    #[error("Cortex lock error: {0}")]
+    Lock(#[from] LockError),
+    #[error("Cortex database version error: {0}")]
+    UnsupportedDbVersion(#[from] UnsupportedDbVersionError),
+    #[error("Cortex execution mode error: {0}")]
+    ExecutionMode(#[from] ExecutionModeError),
+}
+
+impl CortexError {
+    /// Whether the failure can go away when the work is run again
+    pub fn is_transient(&self) -> bool {
+        matches!(self, CortexError::Database(error) if error.is_transient())
+    }
 }
 
 #[derive(Error, Debug)]
@@ -36,3 +114,54 @@ pub struct StepValidationError(pub String);
 #[derive(Error, Debug)]
 #[error("schema version error {0}")]
 pub struct SchemaVersionError(pub String);
+
+#[derive(Error, Debug)]
+#[error("destructive change {0}")]
+pub struct DestructiveChangeError(pub String);
+
+#[derive(Error, Debug)]
+#[error("checksum mismatch {0}")]
+pub struct ChecksumMismatchError(pub String);
+
+#[derive(Error, Debug)]
+#[error("lock error {0}")]
+pub struct LockError(pub String);
+
+#[derive(Error, Debug)]
+#[error("execution mode error {0}")]
+pub struct ExecutionModeError(pub String);
+
+#[derive(Error, Debug)]
+#[error("database version {detected} is outside of the supported range {min} to {max}")]
+pub struct UnsupportedDbVersionError {
+    /// version reported by the server
+    pub detected: semver::Version,
+    pub min: semver::Version,
+    pub max: semver::Version,
+}
+
+impl UnsupportedDbVersionError {
+    /// Fails if the detected version is outside of the supported range
+    /// the lower bound is inclusive, the upper bound exclusive
+    /// # Example
+    /// ```
+    /// use cortex::prelude::*;
+    /// let range = (semver::Version::new(15, 0, 0), semver::Version::new(17, 0, 0));
+    /// assert!(UnsupportedDbVersionError::check(&semver::Version::new(16, 2, 0), &range).is_ok());
+    /// let error = UnsupportedDbVersionError::check(&semver::Version::new(17, 0, 0), &range);
+    /// assert_eq!(error.unwrap_err().detected, semver::Version::new(17, 0, 0));
+    /// ```
+    pub fn check(
+        detected: &semver::Version,
+        (min, max): &(semver::Version, semver::Version),
+    ) -> Result<(), Self> {
+        if detected >= min && detected < max {
+            return Ok(());
+        }
+        Err(Self {
+            detected: detected.clone(),
+            min: min.clone(),
+            max: max.clone(),
+        })
+    }
+}
@@ -1,24 +1,107 @@
-use mongodb::options::TransactionOptions;
+use std::{
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use mongodb::{
+    bson::{doc, oid::ObjectId, DateTime, Document},
+    error::ErrorKind,
+    options::{FindOneAndUpdateOptions, FindOptions, TransactionOptions},
+    ClientSession, Collection,
+};
+use sha2::{Digest, Sha256};
+use tracing::Instrument;
 
 use crate::{
     connection::{
-        mongodb::Mongo, CommitError, ConnectionError, ExecuteError, ExecuteType, TransactionError,
+        mongodb::{is_transient, Mongo},
+        CommitError, ConnectionError, ExecuteError, ExecuteType, QueryError, TransactionError,
+        TransientError,
+    },
+    db::{
+        cortex::{
+            history::{
+                execution_mode_name, parse_execution_mode, parse_step_type, step_type_name,
+                HistoryRecord, CORTEX_VERSION,
+            },
+            observer::{emit, run_span, step_span, CortexEvent, CortexObserver},
+            plan::{MigrationPlan, PlannedStatement, PlannedStep},
+            ChecksumMismatchError, CortexError, ExecutionMode, ExecutionModeError, LockError,
+            RetryPolicy, SchemaVersionError, StepValidationError, UnsupportedDbVersionError,
+        },
+        producer::mongodb::{MongoCommand, MongodbStatementProducer, Precondition},
+    },
+    objects::{
+        schema::Schema,
+        statement::{DbAction, ExistencePolicy, Statement},
+        step::{Step, StepTransaction},
     },
-    db::cortex::{CortexError, ExecutionMode, SchemaVersionError, StepValidationError},
-    objects::step::Step,
 };
 
 #[derive(Debug)]
 pub struct CortexMongoConfig {
+    /// The supported versions of the database server
+    /// the lower bound is inclusive, the upper bound exclusive
     pub supported_db_versions: (semver::Version, semver::Version),
+    /// The execution mode of cortex, all or nothing runs are not supported
     pub execution_mode: ExecutionMode,
+    /// Replay all added steps into a [`Schema`] before executing
+    /// catches statements on collections that were never created
+    pub validate_schema: bool,
+    /// Apply steps below the current version that were never applied
+    /// like steps merged late from another branch, fails the run if not set
+    pub allow_out_of_order: bool,
+    /// How long to wait for the migration lock held by another deployer
+    /// waits until the lock is free if not set
+    pub migration_lock_timeout: Option<Duration>,
+    /// How long the lock is held before other deployers may take it over
+    /// protects against deployers that crashed while migrating
+    /// the lease is renewed before every step, a single step has to finish within it
+    pub migration_lock_lease: Duration,
+    /// How long a command may run, sent as maxTimeMS
+    /// commands are not limited if not set
+    pub statement_timeout: Option<Duration>,
+    /// How often steps failing with transient transaction errors are run again
+    pub retry: RetryPolicy,
 }
 
+impl Default for CortexMongoConfig {
+    fn default() -> Self {
+        Self {
+            supported_db_versions: (
+                semver::Version::new(0, 0, 0),
+                semver::Version::new(u64::MAX, u64::MAX, u64::MAX),
+            ),
+            execution_mode: ExecutionMode::default(),
+            validate_schema: false,
+            allow_out_of_order: false,
+            migration_lock_timeout: None,
+            migration_lock_lease: Duration::from_secs(10 * 60),
+            statement_timeout: None,
+            retry: RetryPolicy::default(),
+        }
+    }
+}
+
+/// id of the lease document held while cortex migrates
+const LOCK_ID: &str = "cortex";
+/// how often a deployer checks if the lock became free
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// mongodb error code of a duplicate key
+const DUPLICATE_KEY: i32 = 11000;
+
 pub struct CortexMongo {
     data: Vec<Step>,
     connection: Mongo,
     config: CortexMongoConfig,
     current_schema_version: semver::Version,
+    /// The versions applied to the database as of the last run
+    applied: Vec<semver::Version>,
+    /// identifies this instance as the holder of the migration lock
+    lock_owner: ObjectId,
+    /// observers notified about the progress of a run
+    observers: Vec<Rc<dyn CortexObserver>>,
 }
 
 impl CortexMongo {
@@ -28,10 +111,19 @@ impl CortexMongo {
             data: Vec::new(),
             connection,
             current_schema_version: current_version,
+            applied: Vec::new(),
             config,
+            lock_owner: ObjectId::new(),
+            observers: Vec::new(),
         }
     }
 
+    /// Adds an observer notified about the progress of every run
+    pub fn add_observer(mut self, observer: impl CortexObserver + 'static) -> Self {
+        self.observers.push(Rc::new(observer));
+        self
+    }
+
     pub fn add_step(mut self, step: Step) -> Self {
         self.data.push(step);
         self
@@ -42,77 +134,850 @@ impl CortexMongo {
         self
     }
 
-    #[allow(dead_code)]
-    fn setup_initial_version(&mut self) -> Result<(), ExecuteError> {
-        todo!()
+    /// the collection the applied versions are stored in
+    /// lives in the database of the connection uri
+    fn version_collection(&self) -> Collection<Document> {
+        self.connection
+            .0
+            .default_database()
+            .unwrap_or_else(|| self.connection.0.database("admin"))
+            .collection("__version__")
     }
-    #[allow(dead_code)]
-    fn set_version(&mut self, _version: &semver::Version) -> Result<(), ExecuteError> {
-        todo!()
+
+    /// the collection holding the lease document of the migration lock
+    fn lock_collection(&self) -> Collection<Document> {
+        self.connection
+            .0
+            .default_database()
+            .unwrap_or_else(|| self.connection.0.database("admin"))
+            .collection("__lock__")
     }
 
-    pub async fn execute(self) -> Result<Self, CortexError> {
-        match self.config.execution_mode {
-            ExecutionMode::Optimistic => self.execute_as_optimistic().await,
-            // requires mongodb replica set
-            ExecutionMode::Transactional => self.execute_as_transaction().await,
+    /// takes the lease when it is free, expired or already ours
+    /// a lease held by another deployer fails the upsert with a duplicate key
+    async fn try_lock(&self) -> Result<bool, CortexError> {
+        let now = DateTime::now();
+        let expires_at = DateTime::from_millis(
+            now.timestamp_millis() + self.config.migration_lock_lease.as_millis() as i64,
+        );
+        let result = self
+            .lock_collection()
+            .find_one_and_update(
+                doc! {
+                    "_id": LOCK_ID,
+                    "$or": [{ "expires_at": { "$lt": now } }, { "owner": self.lock_owner }],
+                },
+                doc! { "$set": { "owner": self.lock_owner, "expires_at": expires_at } },
+                FindOneAndUpdateOptions::builder().upsert(true).build(),
+            )
+            .await;
+        match result {
+            Ok(_) => Ok(true),
+            Err(e) if matches!(*e.kind, ErrorKind::Command(ref c) if c.code == DUPLICATE_KEY) => {
+                Ok(false)
+            }
+            Err(e) => Err(ConnectionError::QueryError(QueryError(e.to_string())))?,
         }
     }
 
-    async fn execute_as_optimistic(mut self) -> Result<Self, CortexError> {
-        if self.data.is_empty() {
-            return Err(StepValidationError(
-                "no steps have been added to the producer".to_string(),
-            ))?;
+    /// extends the lease held by this instance
+    /// fails if the lease expired and another deployer took the lock over
+    async fn renew_lock(&self) -> Result<(), CortexError> {
+        let now = DateTime::now();
+        let expires_at = DateTime::from_millis(
+            now.timestamp_millis() + self.config.migration_lock_lease.as_millis() as i64,
+        );
+        let renewed = self
+            .lock_collection()
+            .find_one_and_update(
+                doc! { "_id": LOCK_ID, "owner": self.lock_owner },
+                doc! { "$set": { "expires_at": expires_at } },
+                None,
+            )
+            .await
+            .map_err(|e| ConnectionError::QueryError(QueryError(e.to_string())))?;
+        match renewed {
+            Some(_) => Ok(()),
+            None => Err(LockError(
+                "migration lock lease expired and was taken over by another deployer".to_string(),
+            ))?,
         }
-        if self
-            .data
+    }
+
+    /// The version of the mongodb server as reported by `buildInfo`
+    pub async fn server_version(&self) -> Result<semver::Version, CortexError> {
+        let info = self
+            .connection
+            .0
+            .database("admin")
+            .run_command(doc! { "buildInfo": 1 }, None)
+            .await
+            .map_err(|e| ConnectionError::QueryError(QueryError(e.to_string())))?;
+        let version = info
+            .get_str("version")
+            .ok()
+            .and_then(|v| semver::Version::parse(v).ok())
+            .ok_or_else(|| {
+                ConnectionError::QueryError(QueryError(format!("invalid build info {}", info)))
+            })?;
+        Ok(version)
+    }
+
+    /// refuses to work on a server outside of the supported versions
+    async fn check_db_version(&self) -> Result<(), CortexError> {
+        let detected = self.server_version().await?;
+        UnsupportedDbVersionError::check(&detected, &self.config.supported_db_versions)?;
+        Ok(())
+    }
+
+    /// waits for the lease so only one deployer migrates at a time
+    async fn acquire_lock(&self) -> Result<(), CortexError> {
+        let started = Instant::now();
+        while !self.try_lock().await? {
+            if let Some(timeout) = self.config.migration_lock_timeout {
+                if started.elapsed() >= timeout {
+                    return Err(LockError(format!(
+                        "migration lock is still held by another deployer after {:?}",
+                        timeout
+                    )))?;
+                }
+            }
+            tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+        }
+        Ok(())
+    }
+
+    async fn release_lock(&self) -> Result<(), CortexError> {
+        self.lock_collection()
+            .delete_one(doc! { "_id": LOCK_ID, "owner": self.lock_owner }, None)
+            .await
+            .map_err(|e| ExecuteError("release lock".to_string(), e.to_string()))
+            .map_err(ConnectionError::ExecuteError)?;
+        Ok(())
+    }
+
+    /// all versions that have been applied to the database
+    async fn applied_versions(&self) -> Result<Vec<semver::Version>, QueryError> {
+        let mut cursor = self
+            .version_collection()
+            .find(doc! { "success": { "$ne": false } }, None)
+            .await
+            .map_err(|e| QueryError(e.to_string()))?;
+        let mut versions = Vec::new();
+        while cursor
+            .advance()
+            .await
+            .map_err(|e| QueryError(e.to_string()))?
+        {
+            let document = cursor
+                .deserialize_current()
+                .map_err(|e| QueryError(e.to_string()))?;
+            let version = document
+                .get_str("version")
+                .ok()
+                .and_then(|v| semver::Version::parse(v).ok())
+                .ok_or_else(|| QueryError(format!("invalid version entry {}", document)))?;
+            versions.push(version);
+        }
+        Ok(versions)
+    }
+
+    /// reads the applied versions and the latest of them from the database
+    async fn refresh_versions(&mut self) -> Result<(), CortexError> {
+        self.applied = self
+            .applied_versions()
+            .await
+            .map_err(ConnectionError::QueryError)?;
+        self.current_schema_version = self
+            .applied
+            .iter()
+            .max()
+            .cloned()
+            .unwrap_or(semver::Version::new(0, 0, 0));
+        Ok(())
+    }
+
+    /// records a run of the step in the history
+    /// runs in the session transaction if there is one
+    async fn record(
+        &mut self,
+        step: &Step,
+        duration: Duration,
+        success: bool,
+        session: Option<&mut ClientSession>,
+    ) -> Result<(), CortexError> {
+        let record = doc! {
+            "version": step.version.to_string(),
+            "name": step.name.to_string(),
+            "step_type": step_type_name(&step.s_type),
+            "checksum": checksum(step)?,
+            "applied_at": DateTime::now(),
+            "duration_ms": duration.as_millis() as i64,
+            // steps can leave or ask for a transaction whatever the mode of the config is
+            "execution_mode": execution_mode_name(match self.transactional(step) {
+                true => &ExecutionMode::Transactional,
+                false => &ExecutionMode::Optimistic,
+            }),
+            "success": success,
+            "cortex_version": CORTEX_VERSION,
+        };
+        let collection = self.version_collection();
+        match session {
+            Some(session) => {
+                collection
+                    .insert_one_with_session(record, None, session)
+                    .await
+            }
+            None => collection.insert_one(record, None).await,
+        }
+        .map_err(|e| ExecuteError(format!("set version {}", step.version), e.to_string()))
+        .map_err(ConnectionError::ExecuteError)?;
+        Ok(())
+    }
+
+    /// the checksum each applied version was last recorded with
+    async fn applied_checksums(&self) -> Result<HashMap<semver::Version, String>, QueryError> {
+        let mut cursor = self
+            .version_collection()
+            .find(
+                doc! { "success": { "$ne": false }, "checksum": { "$type": "string" } },
+                FindOptions::builder().sort(doc! { "_id": 1 }).build(),
+            )
+            .await
+            .map_err(|e| QueryError(e.to_string()))?;
+        let mut checksums = HashMap::new();
+        while cursor
+            .advance()
+            .await
+            .map_err(|e| QueryError(e.to_string()))?
+        {
+            let document = cursor
+                .deserialize_current()
+                .map_err(|e| QueryError(e.to_string()))?;
+            let version = document
+                .get_str("version")
+                .ok()
+                .and_then(|v| semver::Version::parse(v).ok())
+                .ok_or_else(|| QueryError(format!("invalid version entry {}", document)))?;
+            let checksum = document
+                .get_str("checksum")
+                .map_err(|e| QueryError(e.to_string()))?;
+            checksums.insert(version, checksum.to_string());
+        }
+        Ok(checksums)
+    }
+
+    /// fails if an added step was changed after it has been applied
+    async fn verify_checksums(&self) -> Result<(), CortexError> {
+        let applied = self
+            .applied_checksums()
+            .await
+            .map_err(ConnectionError::QueryError)?;
+        for step in &self.data {
+            if let Some(stored) = applied.get(&step.version) {
+                let current = checksum(step)?;
+                if *stored != current {
+                    return Err(ChecksumMismatchError(format!(
+                        "step {} {} was changed after it was applied, run repair to accept the change",
+                        step.name, step.version
+                    )))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Accepts changes made to already applied steps
+    /// by storing the checksums of the added steps for their applied versions
+    pub async fn repair(mut self) -> Result<Self, CortexError> {
+        self.acquire_lock().await?;
+        let result = self.repair_locked().await;
+        let released = self.release_lock().await;
+        result.and(released)?;
+        Ok(self)
+    }
+
+    async fn repair_locked(&mut self) -> Result<(), CortexError> {
+        self.check_db_version().await?;
+        let applied = self
+            .applied_versions()
+            .await
+            .map_err(ConnectionError::QueryError)?;
+        for step in std::mem::take(&mut self.data)
             .iter()
-            .filter(|step| step.version > self.current_schema_version)
-            .count()
-            == 0
+            .filter(|step| applied.contains(&step.version))
         {
-            return Err(SchemaVersionError(
-                "no steps to update everything on the latest version".to_string(),
-            ))?;
+            self.version_collection()
+                .update_many(
+                    doc! { "version": step.version.to_string(), "success": { "$ne": false } },
+                    doc! { "$set": { "checksum": checksum(step)?, "name": step.name.to_string() } },
+                    None,
+                )
+                .await
+                .map_err(|e| {
+                    ExecuteError(format!("repair version {}", step.version), e.to_string())
+                })
+                .map_err(ConnectionError::ExecuteError)?;
+        }
+        Ok(())
+    }
+
+    /// All runs of steps recorded in the database in the order they happened
+    /// including the failed ones
+    pub async fn history(&self) -> Result<Vec<HistoryRecord>, CortexError> {
+        let query_error =
+            |e: mongodb::error::Error| ConnectionError::QueryError(QueryError(e.to_string()));
+        let mut cursor = self
+            .version_collection()
+            .find(None, FindOptions::builder().sort(doc! { "_id": 1 }).build())
+            .await
+            .map_err(query_error)?;
+        let mut records = Vec::new();
+        while cursor.advance().await.map_err(query_error)? {
+            let document = cursor.deserialize_current().map_err(query_error)?;
+            let version = document
+                .get_str("version")
+                .ok()
+                .and_then(|v| semver::Version::parse(v).ok())
+                .ok_or_else(|| {
+                    ConnectionError::QueryError(QueryError(format!(
+                        "invalid version entry {}",
+                        document
+                    )))
+                })?;
+            // records of older versions only carry the id they were inserted with
+            let applied_at = document
+                .get_datetime("applied_at")
+                .copied()
+                .or_else(|_| document.get_object_id("_id").map(|id| id.timestamp()))
+                .unwrap_or(DateTime::from_millis(0));
+            records.push(HistoryRecord {
+                version,
+                name: document.get_str("name").ok().map(String::from),
+                step_type: document.get_str("step_type").ok().and_then(parse_step_type),
+                checksum: document.get_str("checksum").ok().map(String::from),
+                applied_at: applied_at.to_system_time(),
+                duration: document
+                    .get_i64("duration_ms")
+                    .ok()
+                    .map(|ms| Duration::from_millis(ms as u64)),
+                execution_mode: document
+                    .get_str("execution_mode")
+                    .ok()
+                    .and_then(parse_execution_mode),
+                success: document.get_bool("success").unwrap_or(true),
+                cortex_version: document.get_str("cortex_version").ok().map(String::from),
+            });
+        }
+        Ok(records)
+    }
+
+    /// if a step is reverted remove its version from the database
+    /// runs in the session transaction if there is one
+    async fn remove_version(
+        &mut self,
+        version: &semver::Version,
+        session: Option<&mut ClientSession>,
+    ) -> Result<(), ExecuteError> {
+        let filter = doc! { "version": version.to_string() };
+        let collection = self.version_collection();
+        match session {
+            Some(session) => {
+                collection
+                    .delete_many_with_session(filter, None, session)
+                    .await
+            }
+            None => collection.delete_many(filter, None).await,
+        }
+        .map_err(|e| ExecuteError(format!("remove version {}", version), e.to_string()))?;
+        Ok(())
+    }
+
+    /// Reverts all applied steps above the given version in descending order
+    /// and removes their versions from the database
+    pub async fn rollback_to(mut self, version: &semver::Version) -> Result<Self, CortexError> {
+        self.acquire_lock().await?;
+        let rollback = tracing::info_span!("cortex_rollback", database = "mongodb", to = %version);
+        let result = self.rollback_locked(version).instrument(rollback).await;
+        let released = self.release_lock().await;
+        result.and(released)?;
+        Ok(self)
+    }
+
+    async fn rollback_locked(&mut self, version: &semver::Version) -> Result<(), CortexError> {
+        self.check_db_version().await?;
+        self.verify_checksums().await?;
+        let applied = self
+            .applied_versions()
+            .await
+            .map_err(ConnectionError::QueryError)?;
+        if let Some(missing) = applied
+            .iter()
+            .filter(|v| *v > version)
+            .find(|v| !self.data.iter().any(|step| step.version == **v))
+        {
+            return Err(SchemaVersionError(format!(
+                "no step has been added for the applied version {}",
+                missing
+            )))?;
+        }
+        let mut steps = std::mem::take(&mut self.data)
+            .into_iter()
+            .filter(|step| step.version > *version && applied.contains(&step.version))
+            .collect::<Vec<Step>>();
+        if steps.is_empty() {
+            return Err(SchemaVersionError(format!(
+                "no applied steps to roll back to version {}",
+                version
+            )))?;
+        }
+        steps.sort_by(|a, b| b.version.cmp(&a.version));
+        // every step has to be revertible within the data it allows to lose before anything is touched
+        let mut reversals = Vec::new();
+        for step in steps {
+            step.check_reverse_safety()?;
+            let statements = step.reverse_statements()?;
+            for (statement, action) in &statements {
+                MongodbStatementProducer::validate(statement, action)?;
+            }
+            self.check_transaction_blockers(&step, &statements)?;
+            reversals.push((step, statements));
         }
-        for step in self.data {
-            if step.version > self.current_schema_version {
-                for statement in step.statements {
-                    self.connection
-                        .execute(ExecuteType::Driver(statement.0, statement.1), None)
+        for (step, statements) in reversals {
+            self.renew_lock().await?;
+            let max_time = self.max_time(&step);
+            match self.transactional(&step) {
+                true => {
+                    let mut session = self.connection.0.start_session(None).await.map_err(|e| {
+                        ConnectionError::TransactionError(TransactionError(e.to_string()))
+                    })?;
+                    session
+                        .start_transaction(TransactionOptions::builder().build())
+                        .await
+                        .map_err(|e| {
+                            ConnectionError::TransactionError(TransactionError(e.to_string()))
+                        })?;
+                    for (statement, action) in statements {
+                        execute_statement(
+                            &mut self.connection,
+                            statement,
+                            action,
+                            step.policy,
+                            Some(&mut session),
+                            max_time,
+                        )
+                        .await?;
+                    }
+                    // the version is only removed if the statements are reverted
+                    self.remove_version(&step.version, Some(&mut session))
+                        .await
+                        .map_err(ConnectionError::ExecuteError)?;
+                    session
+                        .commit_transaction()
+                        .await
+                        .map_err(|e| ConnectionError::CommitError(CommitError(e.to_string())))?;
+                }
+                false => {
+                    for (statement, action) in statements {
+                        execute_statement(
+                            &mut self.connection,
+                            statement,
+                            action,
+                            step.policy,
+                            None,
+                            max_time,
+                        )
+                        .await?;
+                    }
+                    self.remove_version(&step.version, None)
                         .await
                         .map_err(ConnectionError::ExecuteError)?;
                 }
             }
+            tracing::info!(version = %step.version, name = %step.name, "step reverted");
         }
-        Ok(Self {
-            data: Vec::new(),
-            connection: self.connection,
-            current_schema_version: self.current_schema_version.clone(),
-            config: self.config,
+        self.refresh_versions().await?;
+        Ok(())
+    }
+
+    /// Executes all pending steps in order of their version
+    /// a step is pending if its version is above the latest applied version
+    /// the version is read after the migration lock is acquired
+    /// so waiting deployers skip the steps applied in the meantime
+    /// steps share their objects with `Rc`, the future is not `Send`
+    /// and is spawned on a `LocalSet` instead of the multi threaded runtime
+    /// # Example
+    /// ```no_run
+    /// use cortex::prelude::*;
+    /// async fn migrate(step: Step) -> Result<(), Box<dyn std::error::Error>> {
+    ///     let connection = Mongo::new(ConnectionConfig::<Mongo>::default()).await?;
+    ///     let local = tokio::task::LocalSet::new();
+    ///     local
+    ///         .run_until(tokio::task::spawn_local(
+    ///             CortexMongo::new(connection, CortexMongoConfig::default())
+    ///                 .add_step(step)
+    ///                 .execute(),
+    ///         ))
+    ///         .await??;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn execute(mut self) -> Result<Self, CortexError> {
+        self.acquire_lock().await?;
+        let result = self
+            .execute_locked(None)
+            .instrument(run_span("mongodb", None))
+            .await;
+        let released = self.release_lock().await;
+        result.and(released)?;
+        Ok(self)
+    }
+
+    /// Executes the pending steps up to and including the target version
+    /// the steps above it stay pending for a later run
+    pub async fn execute_to(mut self, version: &semver::Version) -> Result<Self, CortexError> {
+        self.acquire_lock().await?;
+        let result = self
+            .execute_locked(Some(version))
+            .instrument(run_span("mongodb", Some(version)))
+            .await;
+        let released = self.release_lock().await;
+        result.and(released)?;
+        Ok(self)
+    }
+
+    async fn execute_locked(
+        &mut self,
+        target: Option<&semver::Version>,
+    ) -> Result<(), CortexError> {
+        let started = Instant::now();
+        let pending = match self.prepare(target).await {
+            Ok(pending) => pending,
+            Err(error) => {
+                tracing::error!(error = %error, "run failed");
+                let event = CortexEvent::Failed {
+                    step: None,
+                    error: &error,
+                };
+                emit(&self.observers, event);
+                return Err(error);
+            }
+        };
+        tracing::Span::current().record("steps", pending.len());
+        emit(
+            &self.observers,
+            CortexEvent::RunStarted {
+                steps: pending.len(),
+                statements: pending.iter().map(|step| step.statements.len()).sum(),
+            },
+        );
+        for (index, step) in pending.iter().enumerate() {
+            // steps may outlast the lease, it is renewed before each of them
+            if let Err(error) = self.renew_lock().await {
+                tracing::error!(error = %error, "run failed");
+                let event = CortexEvent::Failed {
+                    step: None,
+                    error: &error,
+                };
+                emit(&self.observers, event);
+                return Err(error);
+            }
+            self.apply_step(step, index, pending.len())
+                .instrument(step_span(step))
+                .await?;
+            self.refresh_versions().await?;
+        }
+        tracing::info!(
+            duration_ms = started.elapsed().as_millis() as u64,
+            "run finished"
+        );
+        emit(
+            &self.observers,
+            CortexEvent::RunFinished {
+                steps: pending.len(),
+                duration: started.elapsed(),
+            },
+        );
+        Ok(())
+    }
+
+    /// reads the state of the database and validates the steps against it
+    /// returns the pending steps in the order they run
+    async fn prepare(
+        &mut self,
+        target: Option<&semver::Version>,
+    ) -> Result<Vec<Step>, CortexError> {
+        self.check_db_version().await?;
+        self.refresh_versions().await?;
+        self.verify_checksums().await?;
+        self.check_target(target)?;
+        self.validate(target)?;
+        self.check_pending(target)?;
+        let mut pending = std::mem::take(&mut self.data)
+            .into_iter()
+            .filter(|step| self.is_pending(step, target))
+            .collect::<Vec<Step>>();
+        pending.sort_by(|a, b| a.version.cmp(&b.version));
+        Ok(pending)
+    }
+
+    /// Runs the pipeline of execute without changing the database
+    /// returns the commands execute would send for the pending steps in order
+    pub async fn dry_run(&mut self) -> Result<Vec<String>, CortexError> {
+        Ok(self.plan().await?.render(
+            "//",
+            "session.startTransaction()",
+            "session.commitTransaction()",
+        ))
+    }
+
+    /// The pending steps and the commands they send to the database
+    /// reads the database but does not change it, the plan is empty if nothing is pending
+    pub async fn plan(&mut self) -> Result<MigrationPlan, CortexError> {
+        self.check_db_version().await?;
+        self.refresh_versions().await?;
+        self.verify_checksums().await?;
+        self.validate(None)?;
+        let mut pending = self.data.clone();
+        pending.sort_by(|a, b| a.version.cmp(&b.version));
+        let mut steps = Vec::new();
+        for step in pending.iter().filter(|step| self.is_pending(step, None)) {
+            let mut statements = Vec::new();
+            for (statement, action) in step.ordered_statements()? {
+                let commands = match &statement {
+                    Statement::Code(_) => Vec::new(),
+                    statement => MongodbStatementProducer::map(statement, &action, &step.policy)
+                        .map_err(ConnectionError::ExecuteError)?
+                        .iter()
+                        .map(render)
+                        .collect(),
+                };
+                statements.push(PlannedStatement {
+                    object: statement.name().to_string(),
+                    action: action.kind().to_string(),
+                    commands,
+                    skipped: false,
+                    safety: step.statement_safety(&statement, &action),
+                });
+            }
+            steps.push(PlannedStep {
+                version: step.version.clone(),
+                name: step.name.to_string(),
+                step_type: step.s_type.clone(),
+                transactional: self.transactional(step),
+                statements,
+            });
+        }
+        Ok(MigrationPlan {
+            current_version: self.current_schema_version.clone(),
+            single_transaction: false,
+            steps,
         })
     }
 
-    async fn execute_as_transaction(mut self) -> Result<Self, CortexError> {
+    /// The latest version applied to the database as of the last run
+    pub fn current_version(&self) -> &semver::Version {
+        &self.current_schema_version
+    }
+
+    /// the one rule for steps which have not been applied yet
+    /// steps above the target version are left for a later run
+    /// steps below the current version only run if out of order steps are allowed
+    fn is_pending(&self, step: &Step, target: Option<&semver::Version>) -> bool {
+        !self.applied.contains(&step.version)
+            && (step.version > self.current_schema_version || self.config.allow_out_of_order)
+            && target.is_none_or(|target| step.version <= *target)
+    }
+
+    /// the target has to be ahead of the database, going back is done by rollback_to
+    fn check_target(&self, target: Option<&semver::Version>) -> Result<(), CortexError> {
+        match target {
+            Some(target) if *target < self.current_schema_version => {
+                Err(SchemaVersionError(format!(
+                    "target version {} is below the current version {}, use rollback_to",
+                    target, self.current_schema_version
+                )))?
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// a run fails if no step is pending, a plan is empty
+    fn check_pending(&self, target: Option<&semver::Version>) -> Result<(), CortexError> {
+        match self.data.iter().any(|step| self.is_pending(step, target)) {
+            true => Ok(()),
+            false => Err(SchemaVersionError(
+                "no steps to update everything on the latest version".to_string(),
+            ))?,
+        }
+    }
+
+    /// validates all added steps before anything is sent to the database
+    /// pending steps have to allow the data they lose
+    fn validate(&self, target: Option<&semver::Version>) -> Result<(), CortexError> {
+        if let ExecutionMode::AllOrNothing = self.config.execution_mode {
+            return Err(ExecutionModeError(
+                "mongodb can not run all steps in one transaction, \
+                steps only run in their own transactions, use the transactional mode"
+                    .to_string(),
+            ))?;
+        }
         if self.data.is_empty() {
             return Err(StepValidationError(
                 "no steps have been added to the producer".to_string(),
             ))?;
         }
-        if self
+        for step in &self.data {
+            step.validate()?;
+            for (statement, action) in &step.statements {
+                MongodbStatementProducer::validate(statement, action)?;
+            }
+        }
+        if self.config.validate_schema {
+            Schema::from_steps(&self.data)?;
+        }
+        if !self.config.allow_out_of_order {
+            if let Some(step) = self.data.iter().find(|step| {
+                step.version < self.current_schema_version && !self.applied.contains(&step.version)
+            }) {
+                return Err(SchemaVersionError(format!(
+                    "step {} {} is below the current version {} but was never applied, \
+                    give it a new version or allow out of order steps",
+                    step.name, step.version, self.current_schema_version
+                )))?;
+            }
+        }
+        for step in self
             .data
             .iter()
-            .filter(|step| step.version > self.current_schema_version)
-            .count()
-            == 0
+            .filter(|step| self.is_pending(step, target))
         {
-            return Err(SchemaVersionError(
-                "no steps to update everything on the latest version".to_string(),
-            ))?;
+            step.check_safety()?;
+            self.check_transaction_blockers(step, &step.statements)?;
+        }
+        Ok(())
+    }
+
+    /// statements of transactional steps have to be allowed in a session transaction
+    fn check_transaction_blockers(
+        &self,
+        step: &Step,
+        statements: &[(Statement, DbAction)],
+    ) -> Result<(), CortexError> {
+        if !self.transactional(step) {
+            return Ok(());
+        }
+        for (statement, action) in statements {
+            if let Some(reason) =
+                MongodbStatementProducer::transaction_blocker(statement, action, &step.policy)
+            {
+                return Err(StepValidationError(format!(
+                    "{} in step {} {}: {}, mark the step with without_transaction",
+                    statement.name(),
+                    step.name,
+                    step.version,
+                    reason
+                )))?;
+            }
         }
-        for step in self.data {
-            if step.version > self.current_schema_version {
+        Ok(())
+    }
+
+    /// runs the step and records the outcome in the history
+    async fn apply_step(
+        &mut self,
+        step: &Step,
+        index: usize,
+        steps: usize,
+    ) -> Result<(), CortexError> {
+        emit(
+            &self.observers,
+            CortexEvent::StepStarted { step, index, steps },
+        );
+        let started = Instant::now();
+        let mut attempt = 1;
+        let result = loop {
+            match self.run_step(step, started).await {
+                // steps without a transaction may have been applied partially
+                Err(error)
+                    if self.transactional(step)
+                        && error.is_transient()
+                        && attempt < self.config.retry.attempts =>
+                {
+                    let delay = self.config.retry.delay(attempt);
+                    tracing::warn!(
+                        attempt,
+                        delay_ms = delay.as_millis() as u64,
+                        error = %error,
+                        "retrying after a transient error"
+                    );
+                    emit(
+                        &self.observers,
+                        CortexEvent::Retrying {
+                            step: Some(step),
+                            attempt,
+                            delay,
+                            error: &error,
+                        },
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+        if result.is_err() {
+            // the failure of the step is more important than the failure to record it
+            let _ = self.record(step, started.elapsed(), false, None).await;
+        }
+        match &result {
+            Ok(()) => {
+                tracing::info!(
+                    duration_ms = started.elapsed().as_millis() as u64,
+                    "step committed"
+                );
+                emit(
+                    &self.observers,
+                    CortexEvent::StepCommitted {
+                        step,
+                        index,
+                        steps,
+                        duration: started.elapsed(),
+                    },
+                )
+            }
+            Err(error) => {
+                tracing::error!(error = %error, "step failed");
+                emit(
+                    &self.observers,
+                    CortexEvent::Failed {
+                        step: Some(step),
+                        error,
+                    },
+                )
+            }
+        }
+        result
+    }
+
+    /// whether the statements of the step run inside a session transaction
+    fn transactional(&self, step: &Step) -> bool {
+        match step.transaction {
+            StepTransaction::Without => false,
+            StepTransaction::Own => true,
+            StepTransaction::Inherit => {
+                matches!(self.config.execution_mode, ExecutionMode::Transactional)
+            }
+        }
+    }
+
+    /// runs the statements of the step in a session transaction
+    /// if the step runs in one, the success is recorded in the same transaction
+    async fn run_step(&mut self, step: &Step, started: Instant) -> Result<(), CortexError> {
+        let mut session = match self.transactional(step) {
+            false => None,
+            // transactions require a mongodb replica set
+            true => {
                 let mut session = self.connection.0.start_session(None).await.map_err(|e| {
                     ConnectionError::TransactionError(TransactionError(e.to_string()))
                 })?;
@@ -123,26 +988,146 @@ impl CortexMongo {
                     .map_err(|e| {
                         ConnectionError::TransactionError(TransactionError(e.to_string()))
                     })?;
-                for statement in step.statements {
-                    self.connection
-                        .execute(
-                            ExecuteType::Driver(statement.0, statement.1),
-                            Some(&mut session),
-                        )
-                        .await
-                        .map_err(ConnectionError::ExecuteError)?;
+                Some(session)
+            }
+        };
+        let statements = step.ordered_statements()?;
+        let count = statements.len();
+        let max_time = self.max_time(step);
+        for (index, (statement, action)) in statements.into_iter().enumerate() {
+            let started = Instant::now();
+            let object = statement.name();
+            let kind = action.kind();
+            let mut redacted = Vec::new();
+            if !matches!(statement, Statement::Code(_)) {
+                let commands = MongodbStatementProducer::map(&statement, &action, &step.policy)
+                    .map_err(ConnectionError::ExecuteError)?;
+                redacted.extend(commands.iter().map(MongoCommand::redacted));
+                for command in commands.iter().map(render) {
+                    emit(
+                        &self.observers,
+                        CortexEvent::StatementRendered {
+                            step,
+                            index,
+                            statements: count,
+                            command: &command,
+                        },
+                    );
                 }
-                session
-                    .commit_transaction()
-                    .await
-                    .map_err(|e| ConnectionError::CommitError(CommitError(e.to_string())))?;
             }
+            execute_statement(
+                &mut self.connection,
+                statement,
+                action,
+                step.policy,
+                session.as_mut(),
+                max_time,
+            )
+            .await?;
+            tracing::debug!(
+                object = %object,
+                action = kind,
+                command = %redacted.join("\n"),
+                duration_ms = started.elapsed().as_millis() as u64,
+                "statement executed"
+            );
+            emit(
+                &self.observers,
+                CortexEvent::StatementExecuted {
+                    step,
+                    index,
+                    statements: count,
+                    duration: started.elapsed(),
+                },
+            );
+        }
+        self.record(step, started.elapsed(), true, session.as_mut())
+            .await?;
+        if let Some(mut session) = session {
+            session
+                .commit_transaction()
+                .await
+                .map_err(|e| match is_transient(&e) {
+                    true => TransientError("commitTransaction".to_string(), e.to_string()).into(),
+                    false => ConnectionError::CommitError(CommitError(e.to_string())),
+                })?;
+        }
+        Ok(())
+    }
+
+    /// the statement timeout of the step, or of the config if the step sets none
+    fn max_time(&self, step: &Step) -> Option<Duration> {
+        step.statement_timeout.or(self.config.statement_timeout)
+    }
+}
+
+/// sha256 over the name of the step and the command documents it renders to on mongodb
+/// code statements only contribute their name
+pub(crate) fn checksum(step: &Step) -> Result<String, CortexError> {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}\n", step.name));
+    for (statement, action) in step.ordered_statements()? {
+        match &statement {
+            Statement::Code(code) => hasher.update(format!("code {}", code.name)),
+            statement => {
+                MongodbStatementProducer::validate(statement, &action)?;
+                for command in MongodbStatementProducer::map(statement, &action, &step.policy)
+                    .map_err(ConnectionError::ExecuteError)?
+                {
+                    hasher.update(format!("{} {}", command.database, command.command))
+                }
+            }
+        }
+        hasher.update("\n");
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// renders a command the way it is run in the mongo shell
+/// the precondition is checked before the command is sent
+fn render(command: &MongoCommand) -> String {
+    let run = format!(
+        "db.getSiblingDB(\"{}\").runCommand({})",
+        command.database, command.command
+    );
+    match &command.precondition {
+        Some(Precondition::CollectionExists(name)) => {
+            format!("// if collection {} exists\n{}", name, run)
+        }
+        Some(Precondition::CollectionMissing(name)) => {
+            format!("// if collection {} is missing\n{}", name, run)
+        }
+        None => run,
+    }
+}
+
+/// runs a single statement, code statements receive the client and the session
+async fn execute_statement(
+    connection: &mut Mongo,
+    statement: Statement,
+    action: DbAction,
+    policy: ExistencePolicy,
+    session: Option<&mut ClientSession>,
+    max_time: Option<Duration>,
+) -> Result<(), ConnectionError> {
+    match statement {
+        Statement::Code(code) => {
+            let run = code
+                .mongodb
+                .as_ref()
+                .expect("code is validated before execution");
+            run(&connection.0, session)
+                .await
+                .map_err(|e| ExecuteError(code.name.to_string(), e.to_string()).into())
+        }
+        statement => {
+            connection
+                .execute(
+                    ExecuteType::Driver(statement, action, policy),
+                    session,
+                    max_time,
+                )
+                .await
         }
-        Ok(Self {
-            data: Vec::new(),
-            connection: self.connection,
-            current_schema_version: self.current_schema_version.clone(),
-            config: self.config,
-        })
     }
 }
@@ -0,0 +1,109 @@
+use std::{rc::Rc, time::Duration};
+
+use crate::{db::cortex::CortexError, objects::step::Step};
+
+/// Events emitted by the orchestrators while they run the pending steps
+/// indices start at 0, the totals are counted before the first step runs
+pub enum CortexEvent<'a> {
+    /// the pending steps are validated and about to run
+    RunStarted { steps: usize, statements: usize },
+    /// the step at the index of the pending steps starts
+    StepStarted {
+        step: &'a Step,
+        index: usize,
+        steps: usize,
+    },
+    /// a statement of the step rendered to the command sent to the database
+    /// statements can render to more than one command
+    StatementRendered {
+        step: &'a Step,
+        index: usize,
+        statements: usize,
+        command: &'a str,
+    },
+    /// a statement of the step has been run
+    StatementExecuted {
+        step: &'a Step,
+        index: usize,
+        statements: usize,
+        duration: Duration,
+    },
+    /// all statements of the step have been run and committed
+    StepCommitted {
+        step: &'a Step,
+        index: usize,
+        steps: usize,
+        duration: Duration,
+    },
+    /// the step failed with a transient error and runs again after the delay
+    /// without a step the whole run is retried, like in all or nothing mode
+    Retrying {
+        step: Option<&'a Step>,
+        attempt: u32,
+        delay: Duration,
+        error: &'a CortexError,
+    },
+    /// the run failed, in the given step if it failed while running one
+    Failed {
+        step: Option<&'a Step>,
+        error: &'a CortexError,
+    },
+    /// all pending steps have been applied
+    RunFinished { steps: usize, duration: Duration },
+}
+
+/// Receives the events of the orchestrators, like for progress bars or timing metrics
+/// closures taking an event are observers
+/// # Example
+/// ```
+/// use std::{cell::Cell, rc::Rc};
+/// use cortex::prelude::*;
+/// let executed = Rc::new(Cell::new(0));
+/// let counter = executed.clone();
+/// let observer = move |event: &CortexEvent| {
+///     if let CortexEvent::StatementExecuted { .. } = event {
+///         counter.set(counter.get() + 1);
+///     }
+/// };
+/// observer.notify(&CortexEvent::StatementExecuted {
+///     step: &Step::new("users", StepType::Update, semver::Version::new(0, 0, 1)),
+///     index: 0,
+///     statements: 1,
+///     duration: std::time::Duration::from_millis(3),
+/// });
+/// assert_eq!(executed.get(), 1);
+/// ```
+pub trait CortexObserver {
+    fn notify(&self, event: &CortexEvent<'_>);
+}
+
+impl<F> CortexObserver for F
+where
+    F: Fn(&CortexEvent<'_>),
+{
+    fn notify(&self, event: &CortexEvent<'_>) {
+        self(event)
+    }
+}
+
+/// the span of a run, the number of steps is recorded once they are known
+pub(crate) fn run_span(database: &str, target: Option<&semver::Version>) -> tracing::Span {
+    tracing::info_span!(
+        "cortex_run",
+        database,
+        target = %target.map_or("latest".to_string(), |target| target.to_string()),
+        steps = tracing::field::Empty,
+    )
+}
+
+/// the span of a step, events of its statements are logged inside of it
+pub(crate) fn step_span(step: &Step) -> tracing::Span {
+    tracing::info_span!("cortex_step", version = %step.version, name = %step.name)
+}
+
+/// sends the event to all observers in the order they were added
+pub(crate) fn emit(observers: &[Rc<dyn CortexObserver>], event: CortexEvent<'_>) {
+    for observer in observers {
+        observer.notify(&event);
+    }
+}
@@ -0,0 +1,116 @@
+use serde::Serialize;
+
+use crate::objects::{statement::ChangeSafety, step::StepType};
+
+#[derive(Debug, Clone, Serialize)]
+/// The pending steps of a run and the commands they send to the database
+/// built by `plan` without changing the database
+/// # Example
+/// ```
+/// use cortex::prelude::*;
+/// let plan = MigrationPlan {
+///     current_version: semver::Version::new(0, 0, 1),
+///     single_transaction: false,
+///     steps: vec![PlannedStep {
+///         version: semver::Version::new(0, 0, 2),
+///         name: "drop users".to_string(),
+///         step_type: StepType::Update,
+///         transactional: true,
+///         statements: vec![PlannedStatement {
+///             object: "users".to_string(),
+///             action: "drop".to_string(),
+///             commands: vec!["DROP TABLE users;".to_string()],
+///             skipped: false,
+///             safety: ChangeSafety::Destructive,
+///         }],
+///     }],
+/// };
+/// assert!(plan.has_data_loss());
+/// let json = serde_json::to_value(&plan).unwrap();
+/// assert_eq!(json["steps"][0]["statements"][0]["safety"], "destructive");
+/// ```
+pub struct MigrationPlan {
+    /// latest version applied to the database
+    pub current_version: semver::Version,
+    /// all steps are committed together or not at all
+    pub single_transaction: bool,
+    /// steps that are run in this order
+    pub steps: Vec<PlannedStep>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// A pending step of a [`MigrationPlan`]
+pub struct PlannedStep {
+    pub version: semver::Version,
+    pub name: String,
+    pub step_type: StepType,
+    /// the statements of the step are committed together or not at all
+    pub transactional: bool,
+    /// statements in the order they are run
+    pub statements: Vec<PlannedStatement>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// A statement of a [`PlannedStep`]
+pub struct PlannedStatement {
+    /// name of the object the statement works on
+    pub object: String,
+    /// kind of the action, see [`crate::objects::statement::DbAction::kind`]
+    pub action: String,
+    /// commands the statement renders to, empty for code run on execution
+    pub commands: Vec<String>,
+    /// the object already exists and the policy of the step skips the create
+    pub skipped: bool,
+    /// how much data the statement loses
+    pub safety: ChangeSafety,
+}
+
+impl MigrationPlan {
+    /// Whether no step is pending
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Whether any statement of the plan loses data
+    pub fn has_data_loss(&self) -> bool {
+        self.steps
+            .iter()
+            .flat_map(|step| &step.statements)
+            .any(|statement| statement.safety > ChangeSafety::Safe)
+    }
+
+    /// renders the plan as lines of a script with the steps as comments
+    pub(crate) fn render(&self, comment: &str, begin: &str, commit: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+        // a single transaction replaces the transactions of the steps
+        let per_step = !self.single_transaction;
+        if self.single_transaction {
+            lines.push(begin.to_string());
+        }
+        for step in &self.steps {
+            lines.push(format!("{} step {} {}", comment, step.name, step.version));
+            if per_step && step.transactional {
+                lines.push(begin.to_string());
+            }
+            for statement in &step.statements {
+                if statement.skipped {
+                    lines.push(format!("{} {} already exists", comment, statement.object));
+                } else if statement.commands.is_empty() && statement.action == "execute" {
+                    lines.push(format!(
+                        "{} code {} runs on execution",
+                        comment, statement.object
+                    ));
+                } else {
+                    lines.extend(statement.commands.iter().cloned());
+                }
+            }
+            if per_step && step.transactional {
+                lines.push(commit.to_string());
+            }
+        }
+        if self.single_transaction {
+            lines.push(commit.to_string());
+        }
+        lines
+    }
+}
@@ -1,15 +1,45 @@
-use std::rc::Rc;
+use std::{
+    collections::HashMap,
+    rc::Rc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use postgres::Row;
+use sha2::{Digest, Sha256};
 
 use crate::{
-    connection::{postgres::Postgres, ConnectionError, ExecuteError, ExecuteType},
+    connection::{
+        postgres::{Postgres, PostgresExecutor},
+        redact_sql, ConnectionError, ExecuteError, ExecuteType, QueryError,
+    },
     db::{
-        cortex::{CortexError, ExecutionMode, SchemaVersionError, StepValidationError},
+        cortex::{
+            history::{
+                execution_mode_name, parse_execution_mode, parse_step_type, step_type_name,
+                HistoryRecord, CORTEX_VERSION,
+            },
+            observer::{emit, run_span, step_span, CortexEvent, CortexObserver},
+            plan::{MigrationPlan, PlannedStatement, PlannedStep},
+            ChecksumMismatchError, CortexError, ExecutionMode, ExecutionModeError, LockError,
+            RetryPolicy, SchemaVersionError, StepValidationError, UnsupportedDbVersionError,
+        },
         producer::postgres::PostgresStatementProducer,
     },
-    objects::step::Step,
+    objects::{
+        code::Code,
+        insert::Value,
+        schema::Schema,
+        statement::{ChangeSafety, DbAction, ExistencePolicy, Statement},
+        step::{Step, StepTransaction},
+    },
     prelude::StepType,
 };
 
+#[cfg(feature = "async")]
+mod asynchronous;
+#[cfg(feature = "async")]
+pub use asynchronous::AsyncCortexPostgres;
+
 #[derive(Clone)]
 pub enum PostgresPlugins {
     /// Postgis is a plugin that allows you to use geospatial data
@@ -18,65 +48,159 @@ pub enum PostgresPlugins {
     Timescale,
 }
 
+impl PostgresPlugins {
+    /// Name of the extension the plugin installs
+    pub fn name(&self) -> &'static str {
+        match self {
+            PostgresPlugins::Postgis => "postgis",
+            PostgresPlugins::Timescale => "timescaledb",
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct CortexPostgresConfig {
     /// Plugins/Extensions that should be installed on the database
     pub plugins: Vec<PostgresPlugins>,
-    /// The supported versions of the database
+    /// The supported versions of the database server
+    /// the lower bound is inclusive, the upper bound exclusive
+    /// so every release of postgres 15 and 16 is in the range from 15.0.0 to 17.0.0
+    /// minor releases like 16.2 are detected as 16.0.2
     pub supported_db_versions: (semver::Version, semver::Version),
     /// The execution mode of cortex
     pub execution_mode: ExecutionMode,
+    /// Replay all added steps into a [`Schema`] before executing
+    /// catches statements on objects that were never created
+    pub validate_schema: bool,
+    /// Apply steps below the current version that were never applied
+    /// like steps merged late from another branch, fails the run if not set
+    pub allow_out_of_order: bool,
+    /// How long to wait for the migration lock held by another deployer
+    /// waits until the lock is free if not set
+    pub migration_lock_timeout: Option<Duration>,
+    /// How long a statement may run before postgres cancels it
+    /// uses the setting of the server if not set
+    pub statement_timeout: Option<Duration>,
+    /// How long a statement may wait for a lock on a busy table
+    /// uses the setting of the server if not set
+    pub lock_timeout: Option<Duration>,
+    /// How often steps failing with transient errors are run again
+    /// lost connections are replaced and the migration lock is taken again before a retry
+    pub retry: RetryPolicy,
+}
+
+impl Default for CortexPostgresConfig {
+    fn default() -> Self {
+        Self {
+            plugins: Vec::new(),
+            supported_db_versions: (
+                semver::Version::new(0, 0, 0),
+                semver::Version::new(u64::MAX, u64::MAX, u64::MAX),
+            ),
+            execution_mode: ExecutionMode::default(),
+            validate_schema: false,
+            allow_out_of_order: false,
+            migration_lock_timeout: None,
+            statement_timeout: None,
+            lock_timeout: None,
+            retry: RetryPolicy::default(),
+        }
+    }
 }
 
-type StatementsLen = usize;
-type CurrentStatement = usize;
-type HookFnParam = (CurrentStatement, StatementsLen);
-type HookFn = dyn Fn(HookFnParam);
-type Hook = Rc<HookFn>;
+/// key of the session level advisory lock held while cortex migrates
+const LOCK_KEY: i64 = 0x636f_7274_6578;
+/// how often a deployer checks if the lock became free
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// a step to roll back and the statements reverting it
+type Reversal = (Step, Vec<(Statement, DbAction)>);
+
+/// statements on the history table shared by the sync and async orchestrator
+const HISTORY_TABLE: &str =
+    "CREATE TABLE IF NOT EXISTS __version__ (version VARCHAR(255) NOT NULL);
+    ALTER TABLE __version__
+        ADD COLUMN IF NOT EXISTS id BIGSERIAL,
+        ADD COLUMN IF NOT EXISTS name TEXT,
+        ADD COLUMN IF NOT EXISTS step_type TEXT,
+        ADD COLUMN IF NOT EXISTS checksum TEXT,
+        ADD COLUMN IF NOT EXISTS applied_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+        ADD COLUMN IF NOT EXISTS duration_ms BIGINT,
+        ADD COLUMN IF NOT EXISTS execution_mode TEXT,
+        ADD COLUMN IF NOT EXISTS success BOOLEAN NOT NULL DEFAULT TRUE,
+        ADD COLUMN IF NOT EXISTS cortex_version TEXT;";
+const HISTORY_EXISTS: &str = "SELECT to_regclass('__version__') IS NOT NULL";
+const APPLIED_VERSIONS: &str = "SELECT version FROM __version__ WHERE success";
+const APPLIED_CHECKSUMS: &str = "SELECT version, checksum FROM __version__
+    WHERE success AND checksum IS NOT NULL ORDER BY id";
+const HISTORY: &str = "SELECT version, name, step_type, checksum, applied_at,
+    duration_ms, execution_mode, success, cortex_version
+    FROM __version__ ORDER BY id";
+const SERVER_VERSION: &str = "SHOW server_version_num";
+const LOCK: &str = "SELECT pg_advisory_lock($1)";
+const TRY_LOCK: &str = "SELECT pg_try_advisory_lock($1)";
+const UNLOCK: &str = "SELECT pg_advisory_unlock($1)";
+const RESET_TIMEOUTS: &str = "RESET statement_timeout; RESET lock_timeout;";
+
+/// Connections the postgres orchestrator runs on
+/// each of them runs code statements with a closure of its own
+pub trait PostgresConnection: Clone {
+    /// checks if the code statement can be run on the connection
+    fn validate_code(code: &Code) -> Result<(), StepValidationError>;
+}
+
+impl PostgresConnection for Postgres {
+    fn validate_code(code: &Code) -> Result<(), StepValidationError> {
+        match code.postgres {
+            Some(_) => Ok(()),
+            None => Err(StepValidationError(format!(
+                "code {} has no postgres variant",
+                code.name
+            ))),
+        }
+    }
+}
 
 #[derive(Clone)]
-pub struct CortexPostgres {
+pub struct CortexPostgres<C = Postgres> {
     /// The steps that should be executed
     data: Vec<Step>,
     /// The connection to the database
-    connection: Postgres,
+    connection: C,
     /// The config of Cortex
     config: CortexPostgresConfig,
     /// The current version of the database
     current_schema_version: semver::Version,
-    /// hooks to run
-    after_execute_hooks: Vec<Hook>,
+    /// The versions applied to the database as of the last run
+    applied: Vec<semver::Version>,
+    /// observers notified about the progress of a run
+    observers: Vec<Rc<dyn CortexObserver>>,
 }
 
-impl CortexPostgres {
-    /// Creates a new instance of CortexPostgres
-    /// Everythig Cortex Prefixed are the main orchestration objects of Cortex
-    /// Which are used to create the database and setups around it
-    pub fn new(mut connection: Postgres, config: CortexPostgresConfig) -> Self {
-        // get the current version of the database
-        let mut current_version = semver::Version::parse("0.0.0").expect("failed to parse version");
-        let version = connection.query(
-            ExecuteType::Command(
-                "SELECT version FROM __version__ ORDER BY version DESC LIMIT 1".to_string(),
-            ),
-            &[],
-        );
-        if let Ok(version) = version {
-            if let Some(version) = version.get(0) {
-                current_version =
-                    semver::Version::parse(version.get(0)).expect("failed to parse version");
-            }
-        }
-        println!("current version: {}", current_version);
+impl<C: PostgresConnection> CortexPostgres<C> {
+    /// the instance returned by a run, it keeps the state of the database but no steps
+    fn finished(&self) -> Self {
         Self {
             data: Vec::new(),
-            connection,
-            current_schema_version: current_version,
-            config,
-            after_execute_hooks: Vec::new(),
+            connection: self.connection.clone(),
+            current_schema_version: self.current_schema_version.clone(),
+            applied: self.applied.clone(),
+            config: self.config.clone(),
+            observers: self.observers.clone(),
         }
     }
 
+    /// The latest version applied to the database as of the last run
+    pub fn current_version(&self) -> &semver::Version {
+        &self.current_schema_version
+    }
+
+    /// Adds an observer notified about the progress of every run
+    pub fn add_observer(mut self, observer: impl CortexObserver + 'static) -> Self {
+        self.observers.push(Rc::new(observer));
+        self
+    }
+
     /// Adds a step to cortex
     pub fn add_step(mut self, step: Step) -> Self {
         self.data.push(step);
@@ -96,185 +220,1231 @@ impl CortexPostgres {
         self
     }
 
-    /// private method of db setup for postgres
-    /// this is run on InitSetup Step
-    fn setup_initial_version(&mut self) -> Result<(), ExecuteError> {
-        self.connection.execute(ExecuteType::Command(
-            "CREATE TABLE IF NOT EXISTS __version__ (version VARCHAR(255) NOT NULL)".to_string(),
-        ))?;
-        self.connection.execute(ExecuteType::Command(
-            "INSERT INTO __version__ (version) VALUES ('0.0.0')".to_string(),
-        ))?;
-        self.config.plugins.iter().for_each(|plugin| {
-            match plugin {
-                PostgresPlugins::Postgis => self.connection.execute(ExecuteType::Command(
-                    "CREATE EXTENSION IF NOT EXISTS postgis".to_string(),
-                )),
-                PostgresPlugins::Timescale => self.connection.execute(ExecuteType::Command(
-                    "CREATE EXTENSION IF NOT EXISTS timescaledb CASCADE".to_string(),
-                )),
+    /// the extensions installed by init setup steps
+    fn plugin_commands(&self) -> Vec<ExecuteType> {
+        self.config
+            .plugins
+            .iter()
+            .map(|plugin| {
+                ExecuteType::Command(match plugin {
+                    PostgresPlugins::Postgis => {
+                        "CREATE EXTENSION IF NOT EXISTS postgis".to_string()
+                    }
+                    PostgresPlugins::Timescale => {
+                        "CREATE EXTENSION IF NOT EXISTS timescaledb CASCADE".to_string()
+                    }
+                })
+            })
+            .collect()
+    }
+
+    /// the history entry of a run of the step
+    fn record_command(
+        &self,
+        step: &Step,
+        duration: Duration,
+        success: bool,
+    ) -> Result<ExecuteType, CortexError> {
+        Ok(ExecuteType::Prepared(
+            "INSERT INTO __version__
+                (version, name, step_type, checksum, duration_ms, execution_mode, success, cortex_version)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"
+                .to_string(),
+            vec![
+                Value::Text(step.version.to_string()),
+                Value::Text(step.name.to_string()),
+                Value::Text(step_type_name(&step.s_type).to_string()),
+                Value::Text(checksum(step)?),
+                Value::BigInt(duration.as_millis() as i64),
+                Value::Text(execution_mode_name(&self.step_mode(step)).to_string()),
+                Value::Bool(success),
+                Value::Text(CORTEX_VERSION.to_string()),
+            ],
+        ))
+    }
+
+    /// keeps the applied versions and the latest of them
+    fn set_applied(&mut self, applied: Vec<semver::Version>) {
+        self.current_schema_version = applied
+            .iter()
+            .max()
+            .cloned()
+            .unwrap_or(semver::Version::new(0, 0, 0));
+        self.applied = applied;
+    }
+
+    /// fails if an added step was changed after it has been applied
+    fn check_checksums(
+        &self,
+        applied: &HashMap<semver::Version, String>,
+    ) -> Result<(), CortexError> {
+        for step in &self.data {
+            if let Some(stored) = applied.get(&step.version) {
+                let current = checksum(step)?;
+                if *stored != current {
+                    return Err(ChecksumMismatchError(format!(
+                        "step {} {} was changed after it was applied, run repair to accept the change",
+                        step.name, step.version
+                    )))?;
+                }
             }
-            .expect("failed to create extension");
-        });
+        }
         Ok(())
     }
 
-    /// if a step is executed update the version of the database
-    fn set_version(&mut self, version: &semver::Version) -> Result<(), ExecuteError> {
-        self.connection.execute(ExecuteType::Command(format!(
-            "INSERT INTO __version__ (version) VALUES ('{}')",
-            version
-        )))?;
-        Ok(())
+    /// the applied steps above the version in descending order with their reverse statements
+    /// every step has to be revertible within the data it allows to lose before anything is touched
+    fn reversals(
+        &self,
+        applied: &[semver::Version],
+        version: &semver::Version,
+    ) -> Result<Vec<Reversal>, CortexError> {
+        if let Some(missing) = applied
+            .iter()
+            .filter(|v| *v > version)
+            .find(|v| !self.data.iter().any(|step| step.version == **v))
+        {
+            return Err(SchemaVersionError(format!(
+                "no step has been added for the applied version {}",
+                missing
+            )))?;
+        }
+        let mut steps = self
+            .data
+            .iter()
+            .filter(|step| step.version > *version && applied.contains(&step.version))
+            .cloned()
+            .collect::<Vec<Step>>();
+        if steps.is_empty() {
+            return Err(SchemaVersionError(format!(
+                "no applied steps to roll back to version {}",
+                version
+            )))?;
+        }
+        steps.sort_by(|a, b| b.version.cmp(&a.version));
+        let mut reversals = Vec::new();
+        for step in steps {
+            step.check_reverse_safety()?;
+            let statements = step.reverse_statements()?;
+            for (statement, action) in &statements {
+                Self::validate_statement(statement, action)?;
+            }
+            reversals.push((step, statements));
+        }
+        Ok(reversals)
     }
 
-    pub fn execute(&mut self) -> Result<Self, CortexError> {
-        match self.config.execution_mode {
-            ExecutionMode::Optimistic => self.execute_as_optimistic(),
-            ExecutionMode::Transactional => self.execute_as_transaction(),
+    /// the plan of a pending step, skipped holds for each of its ordered statements
+    /// whether the object it creates already exists
+    fn planned_step(&self, step: Step, skipped: &[bool]) -> Result<PlannedStep, CortexError> {
+        let mut statements = Vec::new();
+        if let StepType::InitSetup = step.s_type {
+            statements.extend(self.config.plugins.iter().zip(self.plugin_commands()).map(
+                |(plugin, command)| PlannedStatement {
+                    object: plugin.name().to_string(),
+                    action: DbAction::Create.kind().to_string(),
+                    commands: vec![render(&command)],
+                    skipped: false,
+                    safety: ChangeSafety::Safe,
+                },
+            ));
+        }
+        for ((statement, action), skipped) in step.ordered_statements()?.into_iter().zip(skipped) {
+            let commands = match (&statement, skipped) {
+                (Statement::Code(_), _) | (_, true) => Vec::new(),
+                (statement, false) => {
+                    PostgresStatementProducer::map(statement, &action, &step.policy)
+                        .iter()
+                        .map(render)
+                        .collect()
+                }
+            };
+            statements.push(PlannedStatement {
+                object: statement.name().to_string(),
+                action: action.kind().to_string(),
+                commands,
+                skipped: *skipped,
+                safety: step.statement_safety(&statement, &action),
+            });
         }
+        Ok(PlannedStep {
+            version: step.version.clone(),
+            name: step.name.to_string(),
+            transactional: self.transactional(&step),
+            step_type: step.s_type,
+            statements,
+        })
     }
 
-    /// Executes all steps that have been added to cortex
-    fn execute_as_transaction(&mut self) -> Result<Self, CortexError> {
+    /// the one rule for steps which have not been applied yet
+    /// steps above the target version are left for a later run
+    /// steps below the current version only run if out of order steps are allowed
+    fn is_pending(&self, step: &Step, target: Option<&semver::Version>) -> bool {
+        !self.applied.contains(&step.version)
+            && (step.version > self.current_schema_version || self.config.allow_out_of_order)
+            && target.is_none_or(|target| step.version <= *target)
+    }
+
+    /// the target has to be ahead of the database, going back is done by rollback_to
+    fn check_target(&self, target: Option<&semver::Version>) -> Result<(), CortexError> {
+        match target {
+            Some(target) if *target < self.current_schema_version => {
+                Err(SchemaVersionError(format!(
+                    "target version {} is below the current version {}, use rollback_to",
+                    target, self.current_schema_version
+                )))?
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// whether the statements of the step run inside a transaction
+    /// init setup steps create databases and only run in one if they ask for it
+    /// all or nothing runs put every step in the transaction of the run
+    fn transactional(&self, step: &Step) -> bool {
+        match (step.transaction, &self.config.execution_mode, &step.s_type) {
+            (StepTransaction::Without, ..) => false,
+            (StepTransaction::Own, ..) => true,
+            (StepTransaction::Inherit, ExecutionMode::AllOrNothing, _) => true,
+            (StepTransaction::Inherit, ExecutionMode::Transactional, StepType::Update) => true,
+            (StepTransaction::Inherit, ..) => false,
+        }
+    }
+
+    /// the mode the step runs with, steps can leave or ask for a transaction
+    /// whatever the mode of the config is
+    fn step_mode(&self, step: &Step) -> ExecutionMode {
+        match (&self.config.execution_mode, self.transactional(step)) {
+            (ExecutionMode::AllOrNothing, _) => ExecutionMode::AllOrNothing,
+            (_, true) => ExecutionMode::Transactional,
+            (_, false) => ExecutionMode::Optimistic,
+        }
+    }
+
+    /// the timeouts of the step, or of the config if the step sets none
+    fn timeout_settings(&self, step: &Step) -> String {
+        [
+            (
+                "statement_timeout",
+                step.statement_timeout.or(self.config.statement_timeout),
+            ),
+            (
+                "lock_timeout",
+                step.lock_timeout.or(self.config.lock_timeout),
+            ),
+        ]
+        .iter()
+        .filter_map(|(setting, timeout)| {
+            timeout.map(|timeout| format!("SET {} = {};", setting, timeout.as_millis()))
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+    }
+
+    /// checks if the statement can be run on the connection
+    fn validate_statement(
+        statement: &Statement,
+        action: &DbAction,
+    ) -> Result<(), StepValidationError> {
+        match statement {
+            Statement::Code(code) => C::validate_code(code),
+            statement => PostgresStatementProducer::validate(statement, action),
+        }
+    }
+
+    /// a run fails if no step is pending, a plan is empty
+    fn check_pending(&self, target: Option<&semver::Version>) -> Result<(), CortexError> {
+        match self.data.iter().any(|step| self.is_pending(step, target)) {
+            true => Ok(()),
+            false => Err(SchemaVersionError(
+                "no steps to update everything on the latest version".to_string(),
+            ))?,
+        }
+    }
+
+    /// validates all added steps before anything is sent to the database
+    /// pending steps have to allow the data they lose
+    fn validate(&self, target: Option<&semver::Version>) -> Result<(), CortexError> {
         if self.data.is_empty() {
             return Err(StepValidationError(
                 "no steps have been added to the producer".to_string(),
             ))?;
         }
-        if self
+        for step in &self.data {
+            step.validate()?;
+            for (statement, action) in &step.statements {
+                Self::validate_statement(statement, action)?;
+            }
+        }
+        if self.config.validate_schema {
+            Schema::from_steps(&self.data)?;
+        }
+        if !self.config.allow_out_of_order {
+            if let Some(step) = self.data.iter().find(|step| {
+                step.version < self.current_schema_version && !self.applied.contains(&step.version)
+            }) {
+                return Err(SchemaVersionError(format!(
+                    "step {} {} is below the current version {} but was never applied, \
+                    give it a new version or allow out of order steps",
+                    step.name, step.version, self.current_schema_version
+                )))?;
+            }
+        }
+        for step in self
             .data
             .iter()
-            .filter(|step| step.version > self.current_schema_version)
-            .count()
-            == 0
+            .filter(|step| self.is_pending(step, target))
         {
-            return Err(SchemaVersionError(
-                "no steps to update everything on the latest version".to_string(),
-            ))?;
+            step.check_safety()?;
+            let all_or_nothing = matches!(self.config.execution_mode, ExecutionMode::AllOrNothing);
+            if !self.transactional(step) {
+                if all_or_nothing {
+                    return Err(ExecutionModeError(format!(
+                        "step {} {} runs without a transaction and can not be part of \
+                        an all or nothing run, use the transactional mode",
+                        step.name, step.version
+                    )))?;
+                }
+                continue;
+            }
+            let hint = match all_or_nothing {
+                true => {
+                    "run it with the transactional mode and mark the step with without_transaction"
+                }
+                false => "mark the step with without_transaction",
+            };
+            for (statement, action) in &step.statements {
+                if let Some(reason) =
+                    PostgresStatementProducer::transaction_blocker(statement, action)
+                {
+                    return Err(StepValidationError(format!(
+                        "{} in step {} {}: {}, {}",
+                        statement.name(),
+                        step.name,
+                        step.version,
+                        reason,
+                        hint
+                    )))?;
+                }
+            }
         }
-        let all_statements_len = self.count_statements();
-        for step in self.data.clone() {
-            // print version
-            println!("version: {} {}", step.version, self.current_schema_version);
-            if step.version > self.current_schema_version {
-                match step.s_type {
-                    StepType::InitSetup => {
-                        self.setup_initial_version()
-                            .map_err(ConnectionError::ExecuteError)?;
-                        for (statement, action) in &step.statements {
-                            self.connection
-                                .execute(ExecuteType::Command(PostgresStatementProducer::map(
-                                    statement, action,
-                                )))
-                                .map_err(ConnectionError::ExecuteError)?;
-                            for hook in &self.after_execute_hooks {
-                                hook((0, all_statements_len));
-                            }
-                        }
-                        self.set_version(&step.version)
-                            .map_err(ConnectionError::ExecuteError)?;
-                    }
-                    StepType::Update => {
-                        let mut transaction = self
-                            .connection
-                            .create_transaction()
-                            .map_err(ConnectionError::TransactionError)?;
-                        for (statement, action) in &step.statements {
-                            transaction
-                                .execute(ExecuteType::Command(PostgresStatementProducer::map(
-                                    statement, action,
-                                )))
-                                .map_err(ConnectionError::ExecuteError)?;
-                            for hook in &self.after_execute_hooks {
-                                hook((0, all_statements_len));
-                            }
-                        }
+        Ok(())
+    }
+}
+
+impl CortexPostgres {
+    /// Creates a new instance of CortexPostgres
+    /// Everythig Cortex Prefixed are the main orchestration objects of Cortex
+    /// Which are used to create the database and setups around it
+    /// The version is read again before every run, a database without history is on 0.0.0
+    pub fn new(connection: Postgres, config: CortexPostgresConfig) -> Self {
+        let mut cortex = Self {
+            data: Vec::new(),
+            connection,
+            current_schema_version: semver::Version::new(0, 0, 0),
+            applied: Vec::new(),
+            config,
+            observers: Vec::new(),
+        };
+        // the history table does not exist before the first run
+        let _ = cortex.refresh_versions();
+        cortex
+    }
+
+    /// private method of db setup for postgres
+    /// this is run on InitSetup Step
+    fn setup_initial_version(
+        &self,
+        executor: &mut dyn PostgresExecutor,
+    ) -> Result<(), ConnectionError> {
+        for command in self.plugin_commands() {
+            executor.execute(command)?;
+        }
+        Ok(())
+    }
+
+    /// creates the history table and adds the columns missing in
+    /// tables created by older versions of cortex
+    fn ensure_history_table(&mut self) -> Result<(), ConnectionError> {
+        self.connection
+            .execute(ExecuteType::Command(HISTORY_TABLE.to_string()))
+    }
+
+    /// records a run of the step in the history
+    fn record(
+        &self,
+        executor: &mut dyn PostgresExecutor,
+        step: &Step,
+        duration: Duration,
+        success: bool,
+    ) -> Result<(), CortexError> {
+        executor.execute(self.record_command(step, duration, success)?)?;
+        Ok(())
+    }
+
+    /// all versions that have been applied to the database
+    fn applied_versions(&mut self) -> Result<Vec<semver::Version>, QueryError> {
+        self.connection
+            .query(ExecuteType::Command(APPLIED_VERSIONS.to_string()), &[])?
+            .iter()
+            .map(|row| semver::Version::parse(row.get(0)).map_err(|e| QueryError(e.to_string())))
+            .collect()
+    }
+
+    /// reads the applied versions and the latest of them from the database
+    fn refresh_versions(&mut self) -> Result<(), CortexError> {
+        let applied = self
+            .applied_versions()
+            .map_err(ConnectionError::QueryError)?;
+        self.set_applied(applied);
+        Ok(())
+    }
+
+    /// the checksum each applied version was last recorded with
+    fn applied_checksums(&mut self) -> Result<HashMap<semver::Version, String>, QueryError> {
+        let rows = self
+            .connection
+            .query(ExecuteType::Command(APPLIED_CHECKSUMS.to_string()), &[])?;
+        applied_checksums(&rows)
+    }
+
+    /// fails if an added step was changed after it has been applied
+    fn verify_checksums(&mut self) -> Result<(), CortexError> {
+        let applied = self
+            .applied_checksums()
+            .map_err(ConnectionError::QueryError)?;
+        self.check_checksums(&applied)
+    }
+
+    /// Accepts changes made to already applied steps
+    /// by storing the checksums of the added steps for their applied versions
+    pub fn repair(&mut self) -> Result<Self, CortexError> {
+        self.with_lock(Self::repair_locked)
+    }
+
+    fn repair_locked(&mut self) -> Result<Self, CortexError> {
+        self.check_db_version()?;
+        self.ensure_history_table()?;
+        let applied = self
+            .applied_versions()
+            .map_err(ConnectionError::QueryError)?;
+        for step in self
+            .data
+            .iter()
+            .filter(|step| applied.contains(&step.version))
+        {
+            self.connection.execute(repair_command(step)?)?;
+        }
+        Ok(self.finished())
+    }
+
+    /// All runs of steps recorded in the database in the order they happened
+    /// including the failed ones
+    pub fn history(&mut self) -> Result<Vec<HistoryRecord>, CortexError> {
+        self.ensure_history_table()?;
+        let rows = self
+            .connection
+            .query(ExecuteType::Command(HISTORY.to_string()), &[])
+            .map_err(ConnectionError::QueryError)?;
+        rows.iter().map(history_record).collect()
+    }
+
+    /// if a step is reverted remove its version from the database
+    fn remove_version(&mut self, version: &semver::Version) -> Result<(), ConnectionError> {
+        self.connection.execute(remove_version_command(version))
+    }
+
+    /// The version of the postgres server
+    /// `server_version_num` is major * 10000 + minor since postgres 10
+    /// and major * 10000 + minor * 100 + patch before
+    pub fn server_version(&mut self) -> Result<semver::Version, CortexError> {
+        let rows = self
+            .connection
+            .query(ExecuteType::Command(SERVER_VERSION.to_string()), &[])
+            .map_err(ConnectionError::QueryError)?;
+        server_version(&rows)
+    }
 
-                        transaction.commit().map_err(ConnectionError::CommitError)?;
-                        self.set_version(&step.version)
-                            .map_err(ConnectionError::ExecuteError)?;
+    /// refuses to work on a server outside of the supported versions
+    fn check_db_version(&mut self) -> Result<(), CortexError> {
+        let detected = self.server_version()?;
+        UnsupportedDbVersionError::check(&detected, &self.config.supported_db_versions)?;
+        Ok(())
+    }
+
+    /// waits for the advisory lock so only one deployer migrates at a time
+    fn acquire_lock(&mut self) -> Result<(), CortexError> {
+        let Some(timeout) = self.config.migration_lock_timeout else {
+            self.connection
+                .query(ExecuteType::Command(LOCK.to_string()), &[&LOCK_KEY])
+                .map_err(ConnectionError::QueryError)?;
+            return Ok(());
+        };
+        let started = Instant::now();
+        loop {
+            let rows = self
+                .connection
+                .query(ExecuteType::Command(TRY_LOCK.to_string()), &[&LOCK_KEY])
+                .map_err(ConnectionError::QueryError)?;
+            if rows.first().is_some_and(|row| row.get::<_, bool>(0)) {
+                return Ok(());
+            }
+            if started.elapsed() >= timeout {
+                return Err(LockError(format!(
+                    "migration lock is still held by another deployer after {:?}",
+                    timeout
+                )))?;
+            }
+            std::thread::sleep(LOCK_POLL_INTERVAL);
+        }
+    }
+
+    fn release_lock(&mut self) -> Result<(), CortexError> {
+        self.connection
+            .query(ExecuteType::Command(UNLOCK.to_string()), &[&LOCK_KEY])
+            .map_err(ConnectionError::QueryError)?;
+        Ok(())
+    }
+
+    /// runs the operation while holding the migration lock
+    /// the lock is released whether the operation failed or not
+    fn with_lock<T>(
+        &mut self,
+        operation: impl FnOnce(&mut Self) -> Result<T, CortexError>,
+    ) -> Result<T, CortexError> {
+        self.acquire_lock()?;
+        let result = operation(self);
+        let released = self.release_lock();
+        let value = result?;
+        released.map(|_| value)
+    }
+
+    /// Reverts all applied steps above the given version in descending order
+    /// and removes their versions from the database
+    pub fn rollback_to(&mut self, version: &semver::Version) -> Result<Self, CortexError> {
+        self.with_lock(|cortex| cortex.rollback_locked(version))
+    }
+
+    fn rollback_locked(&mut self, version: &semver::Version) -> Result<Self, CortexError> {
+        let _rollback =
+            tracing::info_span!("cortex_rollback", database = "postgres", to = %version).entered();
+        self.check_db_version()?;
+        self.ensure_history_table()?;
+        self.verify_checksums()?;
+        let applied = self
+            .applied_versions()
+            .map_err(ConnectionError::QueryError)?;
+        for (step, statements) in self.reversals(&applied, version)? {
+            match self.transactional(&step) {
+                true => {
+                    let mut transaction = self
+                        .connection
+                        .create_transaction()
+                        .map_err(ConnectionError::TransactionError)?;
+                    for (statement, action) in &statements {
+                        execute_statement(
+                            &mut transaction,
+                            statement,
+                            action,
+                            &step.policy,
+                            &mut |_| {},
+                        )?;
                     }
+                    // the version is only removed if the statements are reverted
+                    transaction.execute(remove_version_command(&step.version))?;
+                    transaction.commit()?;
+                }
+                false => {
+                    for (statement, action) in &statements {
+                        execute_statement(
+                            &mut self.connection,
+                            statement,
+                            action,
+                            &step.policy,
+                            &mut |_| {},
+                        )?;
+                    }
+                    self.remove_version(&step.version)?;
                 }
             }
+            tracing::info!(version = %step.version, name = %step.name, "step reverted");
         }
-        Ok(Self {
-            data: Vec::new(),
-            connection: self.connection.clone(),
-            current_schema_version: self.current_schema_version.clone(),
-            config: self.config.clone(),
-            after_execute_hooks: Vec::new(),
-        })
+        self.refresh_versions()?;
+        Ok(self.finished())
     }
 
-    /// Executes all steps that have been added to cortex
-    fn execute_as_optimistic(&mut self) -> Result<Self, CortexError> {
-        if self.data.is_empty() {
-            return Err(StepValidationError(
-                "no steps have been added to the producer".to_string(),
-            ))?;
+    /// Executes all pending steps in order of their version
+    /// a step is pending if its version is above the latest applied version
+    /// the version is read after the migration lock is acquired
+    /// so waiting deployers skip the steps applied in the meantime
+    pub fn execute(&mut self) -> Result<Self, CortexError> {
+        self.with_lock(|cortex| cortex.execute_locked(None))
+    }
+
+    /// Executes the pending steps up to and including the target version
+    /// the steps above it stay pending for a later run
+    pub fn execute_to(&mut self, version: &semver::Version) -> Result<Self, CortexError> {
+        self.with_lock(|cortex| cortex.execute_locked(Some(version)))
+    }
+
+    fn execute_locked(&mut self, target: Option<&semver::Version>) -> Result<Self, CortexError> {
+        let run = run_span("postgres", target);
+        let _run = run.enter();
+        let started = Instant::now();
+        let pending = match self.prepare(target) {
+            Ok(pending) => pending,
+            Err(error) => {
+                tracing::error!(error = %error, "run failed");
+                let event = CortexEvent::Failed {
+                    step: None,
+                    error: &error,
+                };
+                emit(&self.observers, event);
+                return Err(error);
+            }
+        };
+        run.record("steps", pending.len());
+        emit(
+            &self.observers,
+            CortexEvent::RunStarted {
+                steps: pending.len(),
+                statements: pending.iter().map(|step| step.statements.len()).sum(),
+            },
+        );
+        if let ExecutionMode::AllOrNothing = self.config.execution_mode {
+            self.apply_all(&pending)?;
+            self.refresh_versions()?;
+        } else {
+            for (index, step) in pending.iter().enumerate() {
+                self.apply_step(step, index, pending.len())?;
+                self.refresh_versions()?;
+            }
         }
-        if self
+        tracing::info!(
+            duration_ms = started.elapsed().as_millis() as u64,
+            "run finished"
+        );
+        emit(
+            &self.observers,
+            CortexEvent::RunFinished {
+                steps: pending.len(),
+                duration: started.elapsed(),
+            },
+        );
+        Ok(self.finished())
+    }
+
+    /// reads the state of the database and validates the steps against it
+    /// returns the pending steps in the order they run
+    fn prepare(&mut self, target: Option<&semver::Version>) -> Result<Vec<Step>, CortexError> {
+        self.check_db_version()?;
+        self.ensure_history_table()?;
+        self.refresh_versions()?;
+        self.verify_checksums()?;
+        self.check_target(target)?;
+        self.validate(target)?;
+        self.check_pending(target)?;
+        Ok(self
             .data
             .iter()
-            .filter(|step| step.version >= self.current_schema_version)
-            .count()
-            == 0
-        {
-            return Err(SchemaVersionError(
-                "no steps to update everything on the latest version".to_string(),
-            ))?;
+            .filter(|step| self.is_pending(step, target))
+            .cloned()
+            .collect())
+    }
+
+    /// Runs the pipeline of execute without changing the database
+    /// returns the commands execute would send for the pending steps in order
+    /// # Example
+    /// ```no_run
+    /// use cortex::prelude::*;
+    /// let connection = Postgres::new(ConnectionConfig::<Postgres>::default()).unwrap();
+    /// let users = Table::new("users").add_prop(("id", PropType::Int, None));
+    /// let step = Step::new("users", StepType::Update, semver::Version::new(0, 0, 1))
+    ///     .add_statement(&users, DbAction::Create);
+    /// let commands = CortexPostgres::new(connection, CortexPostgresConfig::default())
+    ///     .add_step(step)
+    ///     .dry_run()
+    ///     .unwrap();
+    /// commands.iter().for_each(|command| println!("{}", command));
+    /// ```
+    pub fn dry_run(&mut self) -> Result<Vec<String>, CortexError> {
+        Ok(self.plan()?.render("--", "BEGIN;", "COMMIT;"))
+    }
+
+    /// The pending steps and the commands they send to the database
+    /// reads the database but does not change it, the plan is empty if nothing is pending
+    pub fn plan(&mut self) -> Result<MigrationPlan, CortexError> {
+        self.check_db_version()?;
+        if self.history_exists().map_err(ConnectionError::QueryError)? {
+            self.refresh_versions()?;
+            self.verify_checksums()?;
+        } else {
+            self.applied.clear();
+            self.current_schema_version = semver::Version::new(0, 0, 0);
         }
-        let all_statements_len = self.count_statements();
+        self.validate(None)?;
+        let mut steps = Vec::new();
         for step in self.data.clone() {
-            if step.version >= self.current_schema_version {
-                match step.s_type {
-                    StepType::InitSetup => {
-                        self.setup_initial_version()
-                            .map_err(ConnectionError::ExecuteError)?;
-                        for (statement, action) in &step.statements {
-                            self.connection
-                                .execute(ExecuteType::Command(PostgresStatementProducer::map(
-                                    statement, action,
-                                )))
-                                .map_err(ConnectionError::ExecuteError)?;
-                            for hook in &self.after_execute_hooks {
-                                hook((0, all_statements_len));
-                            }
+            if !self.is_pending(&step, None) {
+                continue;
+            }
+            let mut skipped = Vec::new();
+            for query in existence_queries(&step)? {
+                skipped.push(match query {
+                    Some(query) => !self
+                        .connection
+                        .query(query, &[])
+                        .map_err(ConnectionError::QueryError)?
+                        .is_empty(),
+                    None => false,
+                });
+            }
+            steps.push(self.planned_step(step, &skipped)?);
+        }
+        Ok(MigrationPlan {
+            current_version: self.current_schema_version.clone(),
+            single_transaction: matches!(self.config.execution_mode, ExecutionMode::AllOrNothing),
+            steps,
+        })
+    }
+
+    /// the history table is only created by the first run
+    fn history_exists(&mut self) -> Result<bool, QueryError> {
+        let rows = self
+            .connection
+            .query(ExecuteType::Command(HISTORY_EXISTS.to_string()), &[])?;
+        Ok(rows.first().is_some_and(|row| row.get::<_, bool>(0)))
+    }
+
+    /// runs the step and records the outcome in the history
+    fn apply_step(&mut self, step: &Step, index: usize, steps: usize) -> Result<(), CortexError> {
+        let _step = step_span(step).entered();
+        emit(
+            &self.observers,
+            CortexEvent::StepStarted { step, index, steps },
+        );
+        let started = Instant::now();
+        let result = match self.transactional(step) {
+            true => self.retry(Some(step), || self.run_step(step, started)),
+            // steps without a transaction may have been applied partially
+            false => self.run_step(step, started),
+        };
+        if result.is_err() {
+            // the failure of the step is more important than the failure to record it
+            let _ = self.record(&mut self.connection.clone(), step, started.elapsed(), false);
+        }
+        match &result {
+            Ok(()) => {
+                tracing::info!(
+                    duration_ms = started.elapsed().as_millis() as u64,
+                    "step committed"
+                );
+                emit(
+                    &self.observers,
+                    CortexEvent::StepCommitted {
+                        step,
+                        index,
+                        steps,
+                        duration: started.elapsed(),
+                    },
+                )
+            }
+            Err(error) => {
+                tracing::error!(error = %error, "step failed");
+                emit(
+                    &self.observers,
+                    CortexEvent::Failed {
+                        step: Some(step),
+                        error,
+                    },
+                )
+            }
+        }
+        result
+    }
+
+    /// runs all pending steps and records them in a single transaction
+    /// a failure rolls back every step of the run before it is recorded
+    /// the steps are reported as committed once the transaction is
+    fn apply_all(&self, pending: &[Step]) -> Result<(), CortexError> {
+        let mut failed = None;
+        let durations = match self.retry(None, || self.run_all(pending, &mut failed)) {
+            Ok(durations) => durations,
+            Err(error) => {
+                tracing::error!(error = %error, "run rolled back");
+                let step = failed.map(|(index, duration)| {
+                    let step = &pending[index];
+                    // the failure of the step is more important than the failure to record it
+                    let _ = self.record(&mut self.connection.clone(), step, duration, false);
+                    step
+                });
+                emit(
+                    &self.observers,
+                    CortexEvent::Failed {
+                        step,
+                        error: &error,
+                    },
+                );
+                return Err(error);
+            }
+        };
+        for (index, (step, duration)) in pending.iter().zip(durations).enumerate() {
+            tracing::info!(
+                version = %step.version,
+                name = %step.name,
+                duration_ms = duration.as_millis() as u64,
+                "step committed"
+            );
+            emit(
+                &self.observers,
+                CortexEvent::StepCommitted {
+                    step,
+                    index,
+                    steps: pending.len(),
+                    duration,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// a single attempt of an all or nothing run, returns how long each step took
+    /// the index of the failed step and its duration are kept in failed
+    fn run_all(
+        &self,
+        pending: &[Step],
+        failed: &mut Option<(usize, Duration)>,
+    ) -> Result<Vec<Duration>, CortexError> {
+        *failed = None;
+        // the transaction borrows the shared client, not the orchestrator
+        let mut connection = self.connection.clone();
+        let mut transaction = connection
+            .create_transaction()
+            .map_err(ConnectionError::TransactionError)?;
+        let mut durations = Vec::new();
+        for (index, step) in pending.iter().enumerate() {
+            let _step = step_span(step).entered();
+            emit(
+                &self.observers,
+                CortexEvent::StepStarted {
+                    step,
+                    index,
+                    steps: pending.len(),
+                },
+            );
+            let started = Instant::now();
+            let result = self
+                .run_in(&mut transaction, step)
+                .and_then(|()| self.record(&mut transaction, step, started.elapsed(), true));
+            if let Err(error) = result {
+                *failed = Some((index, started.elapsed()));
+                return Err(error);
+            }
+            durations.push(started.elapsed());
+        }
+        transaction.commit()?;
+        Ok(durations)
+    }
+
+    /// runs the operation again while it fails with a transient error
+    /// and the retry policy allows another attempt
+    /// a lost connection is replaced before the next attempt
+    fn retry<T>(
+        &self,
+        step: Option<&Step>,
+        mut operation: impl FnMut() -> Result<T, CortexError>,
+    ) -> Result<T, CortexError> {
+        let mut attempt = 1;
+        loop {
+            match operation() {
+                Err(error)
+                    if retryable(&error, self.connection.is_closed())
+                        && attempt < self.config.retry.attempts =>
+                {
+                    let delay = self.config.retry.delay(attempt);
+                    tracing::warn!(
+                        attempt,
+                        delay_ms = delay.as_millis() as u64,
+                        error = %error,
+                        "retrying after a transient error"
+                    );
+                    emit(
+                        &self.observers,
+                        CortexEvent::Retrying {
+                            step,
+                            attempt,
+                            delay,
+                            error: &error,
+                        },
+                    );
+                    std::thread::sleep(delay);
+                    self.restore_connection()?;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// reconnects if the connection was lost, the migration lock ended with
+    /// the old session and is taken again unless another deployer holds it by now
+    fn restore_connection(&self) -> Result<(), CortexError> {
+        if !self.connection.is_closed() {
+            return Ok(());
+        }
+        tracing::warn!("reconnecting after the connection was lost");
+        let mut connection = self.connection.clone();
+        connection
+            .reconnect()
+            .map_err(ConnectionError::ConnectError)?;
+        let rows = connection
+            .query(ExecuteType::Command(TRY_LOCK.to_string()), &[&LOCK_KEY])
+            .map_err(ConnectionError::QueryError)?;
+        match rows.first().is_some_and(|row| row.get::<_, bool>(0)) {
+            true => Ok(()),
+            false => Err(LockError(
+                "migration lock was taken by another deployer while the connection was lost"
+                    .to_string(),
+            ))?,
+        }
+    }
+
+    /// runs the statements of the step, in a transaction if the step runs in one
+    /// the success is recorded in the same transaction so a step is never applied unrecorded
+    fn run_step(&self, step: &Step, started: Instant) -> Result<(), CortexError> {
+        // the transaction borrows the shared client, not the orchestrator
+        let mut connection = self.connection.clone();
+        if self.transactional(step) {
+            let mut transaction = connection
+                .create_transaction()
+                .map_err(ConnectionError::TransactionError)?;
+            self.run_in(&mut transaction, step)?;
+            self.record(&mut transaction, step, started.elapsed(), true)?;
+            transaction.commit()?;
+        } else {
+            self.run_in(&mut connection, step)?;
+            self.record(&mut connection, step, started.elapsed(), true)?;
+        }
+        Ok(())
+    }
+
+    /// runs the statements of the step with the executor
+    /// init setup steps install the plugins first
+    /// the timeouts of the step apply to all of its statements
+    fn run_in(&self, executor: &mut dyn PostgresExecutor, step: &Step) -> Result<(), CortexError> {
+        let statements = step.ordered_statements()?;
+        let timeouts = self.timeout_settings(step);
+        if !timeouts.is_empty() {
+            executor.execute(ExecuteType::Command(timeouts.clone()))?;
+        }
+        let result = match step.s_type {
+            StepType::InitSetup => self.setup_initial_version(executor),
+            StepType::Update => Ok(()),
+        }
+        .and_then(|()| run_statements(executor, step, &statements, &self.observers));
+        if !timeouts.is_empty() {
+            let reset = executor.execute(ExecuteType::Command(RESET_TIMEOUTS.to_string()));
+            // a failed transaction rejects the reset and reverts the settings on rollback
+            if result.is_ok() {
+                reset?;
+            }
+        }
+        result?;
+        Ok(())
+    }
+}
+
+/// sha256 over the name of the step and the commands it renders to on postgres
+/// code statements only contribute their name
+pub(crate) fn checksum(step: &Step) -> Result<String, CortexError> {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}\n", step.name));
+    for (statement, action) in step.ordered_statements()? {
+        match &statement {
+            Statement::Code(code) => hasher.update(format!("code {}", code.name)),
+            statement => {
+                PostgresStatementProducer::validate(statement, &action)?;
+                for command in PostgresStatementProducer::map(statement, &action, &step.policy) {
+                    match command {
+                        ExecuteType::Command(query) => hasher.update(query),
+                        ExecuteType::Prepared(query, values) => {
+                            hasher.update(format!("{} {:?}", query, values))
                         }
-                        self.set_version(&step.version)
-                            .map_err(ConnectionError::ExecuteError)?;
+                        ExecuteType::Driver(..) => {}
                     }
-                    StepType::Update => {
-                        for (statement, action) in &step.statements {
-                            self.connection
-                                .execute(ExecuteType::Command(PostgresStatementProducer::map(
-                                    statement, action,
-                                )))
-                                .map_err(ConnectionError::ExecuteError)?;
-                            for hook in &self.after_execute_hooks {
-                                hook((0, all_statements_len));
-                            }
-                        }
-                        self.set_version(&step.version)
-                            .map_err(ConnectionError::ExecuteError)?;
+                }
+            }
+        }
+        hasher.update("\n");
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// the checksum each applied version was last recorded with
+/// transient errors and work that failed on a lost connection can run again
+/// a commit on a lost connection may have been applied and is never retried
+fn retryable(error: &CortexError, closed: bool) -> bool {
+    match error {
+        CortexError::Database(ConnectionError::CommitError(_)) => false,
+        error => error.is_transient() || closed,
+    }
+}
+
+fn applied_checksums(rows: &[Row]) -> Result<HashMap<semver::Version, String>, QueryError> {
+    let mut checksums = HashMap::new();
+    for row in rows {
+        let version = semver::Version::parse(row.get(0)).map_err(|e| QueryError(e.to_string()))?;
+        checksums.insert(version, row.get(1));
+    }
+    Ok(checksums)
+}
+
+/// a row of the history query
+fn history_record(row: &Row) -> Result<HistoryRecord, CortexError> {
+    let version = semver::Version::parse(row.get(0))
+        .map_err(|e| ConnectionError::QueryError(QueryError(e.to_string())))?;
+    Ok(HistoryRecord {
+        version,
+        name: row.get(1),
+        step_type: row.get::<_, Option<&str>>(2).and_then(parse_step_type),
+        checksum: row.get(3),
+        applied_at: row.get::<_, SystemTime>(4),
+        duration: row
+            .get::<_, Option<i64>>(5)
+            .map(|ms| Duration::from_millis(ms as u64)),
+        execution_mode: row.get::<_, Option<&str>>(6).and_then(parse_execution_mode),
+        success: row.get(7),
+        cortex_version: row.get(8),
+    })
+}
+
+fn server_version(rows: &[Row]) -> Result<semver::Version, CortexError> {
+    parse_server_version(rows.first().map_or("", |row| row.get::<_, &str>(0)))
+}
+
+/// `server_version_num` is major * 10000 + minor since postgres 10
+/// and major * 10000 + minor * 100 + patch before
+/// minor releases of postgres 10 and later only fix bugs and map to the patch
+fn parse_server_version(value: &str) -> Result<semver::Version, CortexError> {
+    let number = value.trim().parse::<u64>().map_err(|_| {
+        ConnectionError::QueryError(QueryError(format!("invalid server_version_num {}", value)))
+    })?;
+    Ok(match number / 10000 {
+        major if major >= 10 => semver::Version::new(major, 0, number % 10000),
+        major => semver::Version::new(major, number / 100 % 100, number % 100),
+    })
+}
+
+/// stores the checksum of the step for its applied version
+fn repair_command(step: &Step) -> Result<ExecuteType, CortexError> {
+    Ok(ExecuteType::Prepared(
+        "UPDATE __version__ SET checksum = $1, name = $2 WHERE version = $3 AND success"
+            .to_string(),
+        vec![
+            Value::Text(checksum(step)?),
+            Value::Text(step.name.to_string()),
+            Value::Text(step.version.to_string()),
+        ],
+    ))
+}
+
+/// removes a reverted version from the history
+fn remove_version_command(version: &semver::Version) -> ExecuteType {
+    ExecuteType::Prepared(
+        "DELETE FROM __version__ WHERE version = $1".to_string(),
+        vec![Value::Text(version.to_string())],
+    )
+}
+
+/// for each ordered statement of the step the query checking
+/// if the object it creates exists, if the policy skips existing objects
+fn existence_queries(step: &Step) -> Result<Vec<Option<ExecuteType>>, CortexError> {
+    Ok(step
+        .ordered_statements()?
+        .iter()
+        .map(
+            |(statement, action)| match (statement, &step.policy, action) {
+                (Statement::Code(_), ..) => None,
+                (statement, ExistencePolicy::IfNotExists, DbAction::Create) => {
+                    PostgresStatementProducer::exists_query(statement)
+                }
+                _ => None,
+            },
+        )
+        .collect())
+}
+
+/// renders a command for logs, bind parameters and literals are left out
+fn redact(command: &ExecuteType) -> String {
+    match command {
+        ExecuteType::Command(query) => redact_sql(query),
+        ExecuteType::Prepared(query, values) => {
+            format!("{} -- {} parameters", redact_sql(query), values.len())
+        }
+        ExecuteType::Driver(statement, ..) => format!("-- driver {}", statement.name()),
+    }
+}
+
+/// renders a command the way it is sent to postgres, bind parameters are appended
+fn render(command: &ExecuteType) -> String {
+    match command {
+        ExecuteType::Command(query) => query.clone(),
+        ExecuteType::Prepared(query, values) => format!("{} -- {:?}", query, values),
+        ExecuteType::Driver(statement, ..) => format!("-- driver {}", statement.name()),
+    }
+}
+
+/// runs the statements of a step one after another and reports each of them
+fn run_statements(
+    executor: &mut dyn PostgresExecutor,
+    step: &Step,
+    statements: &[(Statement, DbAction)],
+    observers: &[Rc<dyn CortexObserver>],
+) -> Result<(), ConnectionError> {
+    for (index, (statement, action)) in statements.iter().enumerate() {
+        let started = Instant::now();
+        let mut commands = Vec::new();
+        execute_statement(executor, statement, action, &step.policy, &mut |command| {
+            commands.push(redact(command));
+            let command = render(command);
+            emit(
+                observers,
+                CortexEvent::StatementRendered {
+                    step,
+                    index,
+                    statements: statements.len(),
+                    command: &command,
+                },
+            );
+        })?;
+        tracing::debug!(
+            object = %statement.name(),
+            action = action.kind(),
+            command = %commands.join("\n"),
+            duration_ms = started.elapsed().as_millis() as u64,
+            "statement executed"
+        );
+        emit(
+            observers,
+            CortexEvent::StatementExecuted {
+                step,
+                index,
+                statements: statements.len(),
+                duration: started.elapsed(),
+            },
+        );
+    }
+    Ok(())
+}
+
+/// runs a single statement, code statements receive the executor itself
+/// creates of existing objects are skipped if the policy allows it
+fn execute_statement(
+    executor: &mut dyn PostgresExecutor,
+    statement: &Statement,
+    action: &DbAction,
+    policy: &ExistencePolicy,
+    on_command: &mut dyn FnMut(&ExecuteType),
+) -> Result<(), ConnectionError> {
+    match statement {
+        Statement::Code(code) => {
+            let run = code
+                .postgres
+                .as_ref()
+                .expect("code is validated before execution");
+            run(executor).map_err(|e| ExecuteError(code.name.to_string(), e.to_string()).into())
+        }
+        statement => {
+            if let (ExistencePolicy::IfNotExists, DbAction::Create) = (policy, action) {
+                if let Some(query) = PostgresStatementProducer::exists_query(statement) {
+                    if !executor.query(query, &[])?.is_empty() {
+                        return Ok(());
                     }
                 }
             }
+            for command in PostgresStatementProducer::map(statement, action, policy) {
+                on_command(&command);
+                executor.execute(command)?;
+            }
+            Ok(())
         }
-        Ok(Self {
-            data: Vec::new(),
-            connection: self.connection.clone(),
-            current_schema_version: self.current_schema_version.clone(),
-            config: self.config.clone(),
-            after_execute_hooks: Vec::new(),
-        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{checksum, parse_server_version};
+    use crate::{
+        db::cortex::UnsupportedDbVersionError,
+        objects::{
+            statement::DbAction,
+            step::{Step, StepType},
+            table::{PropType, Table},
+        },
+    };
+
+    fn users_step(name: &str, users: &Table) -> Step {
+        Step::new(name, StepType::Update, semver::Version::new(0, 0, 1))
+            .add_statement(users, DbAction::Create)
     }
 
-    fn count_statements(&self) -> usize {
-        self.data
-            .iter()
-            .map(|step| step.statements.len())
-            .sum::<usize>()
+    fn users() -> Table {
+        Table::new("users")
+            .add_prop(("id", PropType::Int, None))
+            .add_prop(("name", PropType::Text, None))
+    }
+
+    #[test]
+    fn checksum_is_stable() {
+        // changing it makes every deployment fail on its applied steps
+        assert_eq!(
+            checksum(&users_step("create users", &users())).unwrap(),
+            "54ebf488126b1478e52c2140c22c7f712e4d23ca3244b4318bca03abb34731d0"
+        );
+    }
+
+    #[test]
+    fn checksum_changes_with_the_step() {
+        let applied = checksum(&users_step("create users", &users())).unwrap();
+        let renamed = checksum(&users_step("create customers", &users())).unwrap();
+        let edited = users().add_prop(("email", PropType::Text, None));
+        let edited = checksum(&users_step("create users", &edited)).unwrap();
+        assert_ne!(applied, renamed);
+        assert_ne!(applied, edited);
+    }
+
+    #[test]
+    fn parses_server_version_num() {
+        assert_eq!(
+            parse_server_version("150004").unwrap(),
+            semver::Version::new(15, 0, 4)
+        );
+        assert_eq!(
+            parse_server_version("160000").unwrap(),
+            semver::Version::new(16, 0, 0)
+        );
+        assert_eq!(
+            parse_server_version("90624").unwrap(),
+            semver::Version::new(9, 6, 24)
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_server_version_num() {
+        assert!(parse_server_version("16beta1").is_err());
+        assert!(parse_server_version("").is_err());
+    }
+
+    #[test]
+    fn excludes_the_upper_bound() {
+        let range = (
+            semver::Version::new(15, 0, 0),
+            semver::Version::new(16, 0, 0),
+        );
+        let latest = parse_server_version("150004").unwrap();
+        assert!(UnsupportedDbVersionError::check(&latest, &range).is_ok());
+        let next = parse_server_version("160000").unwrap();
+        let error = UnsupportedDbVersionError::check(&next, &range).unwrap_err();
+        assert_eq!(error.detected, semver::Version::new(16, 0, 0));
     }
 }
@@ -0,0 +1,869 @@
+use std::{
+    cell::Cell,
+    future::Future,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use tracing::Instrument;
+
+use super::{
+    applied_checksums, existence_queries, history_record, redact, remove_version_command, render,
+    repair_command, retryable, server_version, CortexPostgres, CortexPostgresConfig,
+    PostgresConnection, APPLIED_CHECKSUMS, APPLIED_VERSIONS, HISTORY, HISTORY_EXISTS,
+    HISTORY_TABLE, LOCK, LOCK_KEY, LOCK_POLL_INTERVAL, RESET_TIMEOUTS, SERVER_VERSION, TRY_LOCK,
+    UNLOCK,
+};
+use crate::{
+    connection::{
+        postgres::{AsyncPostgres, AsyncPostgresExecutor},
+        ConnectionError, ExecuteError, ExecuteType, QueryError,
+    },
+    db::{
+        cortex::{
+            history::HistoryRecord,
+            observer::{emit, run_span, step_span, CortexEvent, CortexObserver},
+            plan::MigrationPlan,
+            CortexError, ExecutionMode, LockError, StepValidationError, UnsupportedDbVersionError,
+        },
+        producer::postgres::PostgresStatementProducer,
+    },
+    objects::{
+        code::Code,
+        statement::{DbAction, ExistencePolicy, Statement},
+        step::Step,
+    },
+    prelude::StepType,
+};
+
+/// Postgres orchestrator running on an async connection
+/// code statements run their closure set with `on_postgres_async`
+/// steps share their objects with `Rc`, so the futures of the orchestrator are not `Send`
+/// they are awaited directly or spawned on a `tokio::task::LocalSet`
+pub type AsyncCortexPostgres = CortexPostgres<AsyncPostgres>;
+
+/// The advisory lock held while a run migrates
+/// a run whose future is dropped before it releases the lock, like on a timeout,
+/// leaves the unlock to the next command on the connection
+struct MigrationLock {
+    connection: AsyncPostgres,
+    released: bool,
+}
+
+impl MigrationLock {
+    async fn release(mut self) -> Result<(), CortexError> {
+        self.released = true;
+        self.connection
+            .query(ExecuteType::Command(UNLOCK.to_string()), &[&LOCK_KEY])
+            .await
+            .map_err(ConnectionError::QueryError)?;
+        Ok(())
+    }
+}
+
+impl Drop for MigrationLock {
+    fn drop(&mut self) {
+        if !self.released {
+            self.connection
+                .defer(format!("SELECT pg_advisory_unlock({})", LOCK_KEY));
+        }
+    }
+}
+
+impl PostgresConnection for AsyncPostgres {
+    fn validate_code(code: &Code) -> Result<(), StepValidationError> {
+        match code.postgres_async {
+            Some(_) => Ok(()),
+            None => Err(StepValidationError(format!(
+                "code {} has no async postgres variant",
+                code.name
+            ))),
+        }
+    }
+}
+
+impl CortexPostgres<AsyncPostgres> {
+    /// Creates a new instance of CortexPostgres on an async connection
+    /// The version is read again before every run, a database without history is on 0.0.0
+    /// # Example
+    /// ```no_run
+    /// use cortex::prelude::*;
+    /// async fn migrate(step: Step) -> Result<(), Box<dyn std::error::Error>> {
+    ///     let connection = AsyncPostgres::new(ConnectionConfig::<Postgres>::default()).await?;
+    ///     CortexPostgres::new_async(connection, CortexPostgresConfig::default())
+    ///         .await
+    ///         .add_step(step)
+    ///         .execute()
+    ///         .await?;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn new_async(connection: AsyncPostgres, config: CortexPostgresConfig) -> Self {
+        let mut cortex = Self {
+            data: Vec::new(),
+            connection,
+            current_schema_version: semver::Version::new(0, 0, 0),
+            applied: Vec::new(),
+            config,
+            observers: Vec::new(),
+        };
+        // the history table does not exist before the first run
+        let _ = cortex.refresh_versions().await;
+        cortex
+    }
+
+    /// private method of db setup for postgres
+    /// this is run on InitSetup Step
+    async fn setup_initial_version(
+        &self,
+        executor: &mut dyn AsyncPostgresExecutor,
+    ) -> Result<(), ConnectionError> {
+        for command in self.plugin_commands() {
+            executor.execute(command).await?;
+        }
+        Ok(())
+    }
+
+    /// creates the history table and adds the columns missing in
+    /// tables created by older versions of cortex
+    async fn ensure_history_table(&self) -> Result<(), ConnectionError> {
+        self.connection
+            .execute(ExecuteType::Command(HISTORY_TABLE.to_string()))
+            .await
+    }
+
+    /// records a run of the step in the history
+    async fn record(
+        &self,
+        executor: &mut dyn AsyncPostgresExecutor,
+        step: &Step,
+        duration: Duration,
+        success: bool,
+    ) -> Result<(), CortexError> {
+        executor
+            .execute(self.record_command(step, duration, success)?)
+            .await?;
+        Ok(())
+    }
+
+    /// all versions that have been applied to the database
+    async fn applied_versions(&self) -> Result<Vec<semver::Version>, QueryError> {
+        self.connection
+            .query(ExecuteType::Command(APPLIED_VERSIONS.to_string()), &[])
+            .await?
+            .iter()
+            .map(|row| semver::Version::parse(row.get(0)).map_err(|e| QueryError(e.to_string())))
+            .collect()
+    }
+
+    /// reads the applied versions and the latest of them from the database
+    async fn refresh_versions(&mut self) -> Result<(), CortexError> {
+        let applied = self
+            .applied_versions()
+            .await
+            .map_err(ConnectionError::QueryError)?;
+        self.set_applied(applied);
+        Ok(())
+    }
+
+    /// fails if an added step was changed after it has been applied
+    async fn verify_checksums(&self) -> Result<(), CortexError> {
+        let rows = self
+            .connection
+            .query(ExecuteType::Command(APPLIED_CHECKSUMS.to_string()), &[])
+            .await
+            .map_err(ConnectionError::QueryError)?;
+        let applied = applied_checksums(&rows).map_err(ConnectionError::QueryError)?;
+        self.check_checksums(&applied)
+    }
+
+    /// Accepts changes made to already applied steps
+    /// by storing the checksums of the added steps for their applied versions
+    pub async fn repair(&mut self) -> Result<Self, CortexError> {
+        let lock = self.acquire_lock().await?;
+        let result = self.repair_locked().await;
+        let released = lock.release().await;
+        let value = result?;
+        released.map(|_| value)
+    }
+
+    async fn repair_locked(&mut self) -> Result<Self, CortexError> {
+        self.check_db_version().await?;
+        self.ensure_history_table().await?;
+        let applied = self
+            .applied_versions()
+            .await
+            .map_err(ConnectionError::QueryError)?;
+        for step in self
+            .data
+            .iter()
+            .filter(|step| applied.contains(&step.version))
+        {
+            self.connection.execute(repair_command(step)?).await?;
+        }
+        Ok(self.finished())
+    }
+
+    /// All runs of steps recorded in the database in the order they happened
+    /// including the failed ones
+    pub async fn history(&self) -> Result<Vec<HistoryRecord>, CortexError> {
+        self.ensure_history_table().await?;
+        let rows = self
+            .connection
+            .query(ExecuteType::Command(HISTORY.to_string()), &[])
+            .await
+            .map_err(ConnectionError::QueryError)?;
+        rows.iter().map(history_record).collect()
+    }
+
+    /// The version of the postgres server
+    pub async fn server_version(&self) -> Result<semver::Version, CortexError> {
+        let rows = self
+            .connection
+            .query(ExecuteType::Command(SERVER_VERSION.to_string()), &[])
+            .await
+            .map_err(ConnectionError::QueryError)?;
+        server_version(&rows)
+    }
+
+    /// refuses to work on a server outside of the supported versions
+    async fn check_db_version(&self) -> Result<(), CortexError> {
+        let detected = self.server_version().await?;
+        UnsupportedDbVersionError::check(&detected, &self.config.supported_db_versions)?;
+        Ok(())
+    }
+
+    /// waits for the advisory lock so only one deployer migrates at a time
+    /// the guard exists before the lock is requested, a lock granted
+    /// after the future was dropped is released as well
+    async fn acquire_lock(&self) -> Result<MigrationLock, CortexError> {
+        let mut lock = MigrationLock {
+            connection: self.connection.clone(),
+            released: false,
+        };
+        let Some(timeout) = self.config.migration_lock_timeout else {
+            self.connection
+                .query(ExecuteType::Command(LOCK.to_string()), &[&LOCK_KEY])
+                .await
+                .map_err(ConnectionError::QueryError)?;
+            return Ok(lock);
+        };
+        let started = Instant::now();
+        loop {
+            let rows = self
+                .connection
+                .query(ExecuteType::Command(TRY_LOCK.to_string()), &[&LOCK_KEY])
+                .await
+                .map_err(ConnectionError::QueryError)?;
+            if rows.first().is_some_and(|row| row.get::<_, bool>(0)) {
+                return Ok(lock);
+            }
+            if started.elapsed() >= timeout {
+                // the lock was never taken
+                lock.released = true;
+                return Err(LockError(format!(
+                    "migration lock is still held by another deployer after {:?}",
+                    timeout
+                )))?;
+            }
+            tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Reverts all applied steps above the given version in descending order
+    /// and removes their versions from the database
+    pub async fn rollback_to(&mut self, version: &semver::Version) -> Result<Self, CortexError> {
+        let lock = self.acquire_lock().await?;
+        let rollback = tracing::info_span!("cortex_rollback", database = "postgres", to = %version);
+        let result = self.rollback_locked(version).instrument(rollback).await;
+        let released = lock.release().await;
+        let value = result?;
+        released.map(|_| value)
+    }
+
+    async fn rollback_locked(&mut self, version: &semver::Version) -> Result<Self, CortexError> {
+        self.check_db_version().await?;
+        self.ensure_history_table().await?;
+        self.verify_checksums().await?;
+        let applied = self
+            .applied_versions()
+            .await
+            .map_err(ConnectionError::QueryError)?;
+        for (step, statements) in self.reversals(&applied, version)? {
+            match self.transactional(&step) {
+                true => {
+                    let mut transaction = self
+                        .connection
+                        .create_transaction()
+                        .await
+                        .map_err(ConnectionError::TransactionError)?;
+                    for (statement, action) in &statements {
+                        execute_statement(
+                            &mut transaction,
+                            statement,
+                            action,
+                            &step.policy,
+                            &mut |_| {},
+                        )
+                        .await?;
+                    }
+                    // the version is only removed if the statements are reverted
+                    transaction
+                        .execute(remove_version_command(&step.version))
+                        .await?;
+                    transaction.commit().await?;
+                }
+                false => {
+                    for (statement, action) in &statements {
+                        execute_statement(
+                            &mut self.connection.clone(),
+                            statement,
+                            action,
+                            &step.policy,
+                            &mut |_| {},
+                        )
+                        .await?;
+                    }
+                    self.connection
+                        .execute(remove_version_command(&step.version))
+                        .await?;
+                }
+            }
+            tracing::info!(version = %step.version, name = %step.name, "step reverted");
+        }
+        self.refresh_versions().await?;
+        Ok(self.finished())
+    }
+
+    /// Executes all pending steps in order of their version
+    /// a step is pending if its version is above the latest applied version
+    /// the version is read after the migration lock is acquired
+    /// so waiting deployers skip the steps applied in the meantime
+    /// steps share their objects with `Rc`, the future is not `Send`
+    /// and is spawned on a `LocalSet` instead of the multi threaded runtime
+    /// # Example
+    /// ```no_run
+    /// use cortex::prelude::*;
+    /// async fn migrate(step: Step) -> Result<(), Box<dyn std::error::Error>> {
+    ///     let connection = AsyncPostgres::new(ConnectionConfig::<Postgres>::default()).await?;
+    ///     let local = tokio::task::LocalSet::new();
+    ///     local
+    ///         .run_until(async move {
+    ///             tokio::task::spawn_local(async move {
+    ///                 CortexPostgres::new_async(connection, CortexPostgresConfig::default())
+    ///                     .await
+    ///                     .add_step(step)
+    ///                     .execute()
+    ///                     .await
+    ///             })
+    ///             .await
+    ///         })
+    ///         .await??;
+    ///     Ok(())
+    /// }
+    /// ```
+    pub async fn execute(&mut self) -> Result<Self, CortexError> {
+        let lock = self.acquire_lock().await?;
+        let result = self
+            .execute_locked(None)
+            .instrument(run_span("postgres", None))
+            .await;
+        let released = lock.release().await;
+        let value = result?;
+        released.map(|_| value)
+    }
+
+    /// Executes the pending steps up to and including the target version
+    /// the steps above it stay pending for a later run
+    pub async fn execute_to(&mut self, version: &semver::Version) -> Result<Self, CortexError> {
+        let lock = self.acquire_lock().await?;
+        let result = self
+            .execute_locked(Some(version))
+            .instrument(run_span("postgres", Some(version)))
+            .await;
+        let released = lock.release().await;
+        let value = result?;
+        released.map(|_| value)
+    }
+
+    async fn execute_locked(
+        &mut self,
+        target: Option<&semver::Version>,
+    ) -> Result<Self, CortexError> {
+        let started = Instant::now();
+        let pending = match self.prepare(target).await {
+            Ok(pending) => pending,
+            Err(error) => {
+                tracing::error!(error = %error, "run failed");
+                let event = CortexEvent::Failed {
+                    step: None,
+                    error: &error,
+                };
+                emit(&self.observers, event);
+                return Err(error);
+            }
+        };
+        tracing::Span::current().record("steps", pending.len());
+        emit(
+            &self.observers,
+            CortexEvent::RunStarted {
+                steps: pending.len(),
+                statements: pending.iter().map(|step| step.statements.len()).sum(),
+            },
+        );
+        if let ExecutionMode::AllOrNothing = self.config.execution_mode {
+            self.apply_all(&pending).await?;
+            self.refresh_versions().await?;
+        } else {
+            for (index, step) in pending.iter().enumerate() {
+                self.apply_step(step, index, pending.len())
+                    .instrument(step_span(step))
+                    .await?;
+                self.refresh_versions().await?;
+            }
+        }
+        tracing::info!(
+            duration_ms = started.elapsed().as_millis() as u64,
+            "run finished"
+        );
+        emit(
+            &self.observers,
+            CortexEvent::RunFinished {
+                steps: pending.len(),
+                duration: started.elapsed(),
+            },
+        );
+        Ok(self.finished())
+    }
+
+    /// reads the state of the database and validates the steps against it
+    /// returns the pending steps in the order they run
+    async fn prepare(
+        &mut self,
+        target: Option<&semver::Version>,
+    ) -> Result<Vec<Step>, CortexError> {
+        self.check_db_version().await?;
+        self.ensure_history_table().await?;
+        self.refresh_versions().await?;
+        self.verify_checksums().await?;
+        self.check_target(target)?;
+        self.validate(target)?;
+        self.check_pending(target)?;
+        Ok(self
+            .data
+            .iter()
+            .filter(|step| self.is_pending(step, target))
+            .cloned()
+            .collect())
+    }
+
+    /// Runs the pipeline of execute without changing the database
+    /// returns the commands execute would send for the pending steps in order
+    pub async fn dry_run(&mut self) -> Result<Vec<String>, CortexError> {
+        Ok(self.plan().await?.render("--", "BEGIN;", "COMMIT;"))
+    }
+
+    /// The pending steps and the commands they send to the database
+    /// reads the database but does not change it, the plan is empty if nothing is pending
+    pub async fn plan(&mut self) -> Result<MigrationPlan, CortexError> {
+        self.check_db_version().await?;
+        if self
+            .history_exists()
+            .await
+            .map_err(ConnectionError::QueryError)?
+        {
+            self.refresh_versions().await?;
+            self.verify_checksums().await?;
+        } else {
+            self.applied.clear();
+            self.current_schema_version = semver::Version::new(0, 0, 0);
+        }
+        self.validate(None)?;
+        let mut steps = Vec::new();
+        for step in self.data.clone() {
+            if !self.is_pending(&step, None) {
+                continue;
+            }
+            let mut skipped = Vec::new();
+            for query in existence_queries(&step)? {
+                skipped.push(match query {
+                    Some(query) => !self
+                        .connection
+                        .query(query, &[])
+                        .await
+                        .map_err(ConnectionError::QueryError)?
+                        .is_empty(),
+                    None => false,
+                });
+            }
+            steps.push(self.planned_step(step, &skipped)?);
+        }
+        Ok(MigrationPlan {
+            current_version: self.current_schema_version.clone(),
+            single_transaction: matches!(self.config.execution_mode, ExecutionMode::AllOrNothing),
+            steps,
+        })
+    }
+
+    /// the history table is only created by the first run
+    async fn history_exists(&self) -> Result<bool, QueryError> {
+        let rows = self
+            .connection
+            .query(ExecuteType::Command(HISTORY_EXISTS.to_string()), &[])
+            .await?;
+        Ok(rows.first().is_some_and(|row| row.get::<_, bool>(0)))
+    }
+
+    /// runs the step and records the outcome in the history
+    async fn apply_step(
+        &mut self,
+        step: &Step,
+        index: usize,
+        steps: usize,
+    ) -> Result<(), CortexError> {
+        emit(
+            &self.observers,
+            CortexEvent::StepStarted { step, index, steps },
+        );
+        let started = Instant::now();
+        let result = match self.transactional(step) {
+            true => {
+                self.retry(Some(step), || self.run_step(step, started))
+                    .await
+            }
+            // steps without a transaction may have been applied partially
+            false => self.run_step(step, started).await,
+        };
+        if result.is_err() {
+            // the failure of the step is more important than the failure to record it
+            let _ = self
+                .record(&mut self.connection.clone(), step, started.elapsed(), false)
+                .await;
+        }
+        match &result {
+            Ok(()) => {
+                tracing::info!(
+                    duration_ms = started.elapsed().as_millis() as u64,
+                    "step committed"
+                );
+                emit(
+                    &self.observers,
+                    CortexEvent::StepCommitted {
+                        step,
+                        index,
+                        steps,
+                        duration: started.elapsed(),
+                    },
+                )
+            }
+            Err(error) => {
+                tracing::error!(error = %error, "step failed");
+                emit(
+                    &self.observers,
+                    CortexEvent::Failed {
+                        step: Some(step),
+                        error,
+                    },
+                )
+            }
+        }
+        result
+    }
+
+    /// runs all pending steps and records them in a single transaction
+    /// a failure rolls back every step of the run before it is recorded
+    /// the steps are reported as committed once the transaction is
+    async fn apply_all(&self, pending: &[Step]) -> Result<(), CortexError> {
+        let failed = Cell::new(None);
+        let durations = match self.retry(None, || self.run_all(pending, &failed)).await {
+            Ok(durations) => durations,
+            Err(error) => {
+                tracing::error!(error = %error, "run rolled back");
+                let step = match failed.get() {
+                    Some((index, duration)) => {
+                        let step = &pending[index];
+                        // the failure of the step is more important than the failure to record it
+                        let _ = self
+                            .record(&mut self.connection.clone(), step, duration, false)
+                            .await;
+                        Some(step)
+                    }
+                    None => None,
+                };
+                emit(
+                    &self.observers,
+                    CortexEvent::Failed {
+                        step,
+                        error: &error,
+                    },
+                );
+                return Err(error);
+            }
+        };
+        for (index, (step, duration)) in pending.iter().zip(durations).enumerate() {
+            tracing::info!(
+                version = %step.version,
+                name = %step.name,
+                duration_ms = duration.as_millis() as u64,
+                "step committed"
+            );
+            emit(
+                &self.observers,
+                CortexEvent::StepCommitted {
+                    step,
+                    index,
+                    steps: pending.len(),
+                    duration,
+                },
+            );
+        }
+        Ok(())
+    }
+
+    /// a single attempt of an all or nothing run, returns how long each step took
+    /// the index of the failed step and its duration are kept in failed
+    async fn run_all(
+        &self,
+        pending: &[Step],
+        failed: &Cell<Option<(usize, Duration)>>,
+    ) -> Result<Vec<Duration>, CortexError> {
+        failed.set(None);
+        // other commands on the connection wait until the transaction is finished
+        let mut transaction = self
+            .connection
+            .create_transaction()
+            .await
+            .map_err(ConnectionError::TransactionError)?;
+        let mut durations = Vec::new();
+        for (index, step) in pending.iter().enumerate() {
+            emit(
+                &self.observers,
+                CortexEvent::StepStarted {
+                    step,
+                    index,
+                    steps: pending.len(),
+                },
+            );
+            let started = Instant::now();
+            let result = async {
+                self.run_in(&mut transaction, step).await?;
+                self.record(&mut transaction, step, started.elapsed(), true)
+                    .await
+            }
+            .instrument(step_span(step))
+            .await;
+            if let Err(error) = result {
+                failed.set(Some((index, started.elapsed())));
+                return Err(error);
+            }
+            durations.push(started.elapsed());
+        }
+        transaction.commit().await?;
+        Ok(durations)
+    }
+
+    /// runs the operation again while it fails with a transient error
+    /// and the retry policy allows another attempt
+    /// a lost connection is replaced before the next attempt
+    async fn retry<T, F>(
+        &self,
+        step: Option<&Step>,
+        mut operation: impl FnMut() -> F,
+    ) -> Result<T, CortexError>
+    where
+        F: Future<Output = Result<T, CortexError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let result = operation().await;
+            let closed = result.is_err() && self.connection.is_closed().await;
+            match result {
+                Err(error) if retryable(&error, closed) && attempt < self.config.retry.attempts => {
+                    let delay = self.config.retry.delay(attempt);
+                    tracing::warn!(
+                        attempt,
+                        delay_ms = delay.as_millis() as u64,
+                        error = %error,
+                        "retrying after a transient error"
+                    );
+                    emit(
+                        &self.observers,
+                        CortexEvent::Retrying {
+                            step,
+                            attempt,
+                            delay,
+                            error: &error,
+                        },
+                    );
+                    tokio::time::sleep(delay).await;
+                    self.restore_connection().await?;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// reconnects if the connection was lost, the migration lock ended with
+    /// the old session and is taken again unless another deployer holds it by now
+    async fn restore_connection(&self) -> Result<(), CortexError> {
+        if !self.connection.is_closed().await {
+            return Ok(());
+        }
+        tracing::warn!("reconnecting after the connection was lost");
+        self.connection
+            .reconnect()
+            .await
+            .map_err(ConnectionError::ConnectError)?;
+        let rows = self
+            .connection
+            .query(ExecuteType::Command(TRY_LOCK.to_string()), &[&LOCK_KEY])
+            .await
+            .map_err(ConnectionError::QueryError)?;
+        match rows.first().is_some_and(|row| row.get::<_, bool>(0)) {
+            true => Ok(()),
+            false => Err(LockError(
+                "migration lock was taken by another deployer while the connection was lost"
+                    .to_string(),
+            ))?,
+        }
+    }
+
+    /// runs the statements of the step, in a transaction if the step runs in one
+    /// the success is recorded in the same transaction so a step is never applied unrecorded
+    async fn run_step(&self, step: &Step, started: Instant) -> Result<(), CortexError> {
+        if self.transactional(step) {
+            let mut transaction = self
+                .connection
+                .create_transaction()
+                .await
+                .map_err(ConnectionError::TransactionError)?;
+            self.run_in(&mut transaction, step).await?;
+            self.record(&mut transaction, step, started.elapsed(), true)
+                .await?;
+            transaction.commit().await?;
+        } else {
+            let mut connection = self.connection.clone();
+            self.run_in(&mut connection, step).await?;
+            self.record(&mut connection, step, started.elapsed(), true)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// runs the statements of the step with the executor
+    /// init setup steps install the plugins first
+    /// the timeouts of the step apply to all of its statements
+    async fn run_in(
+        &self,
+        executor: &mut dyn AsyncPostgresExecutor,
+        step: &Step,
+    ) -> Result<(), CortexError> {
+        let statements = step.ordered_statements()?;
+        let timeouts = self.timeout_settings(step);
+        if !timeouts.is_empty() {
+            executor
+                .execute(ExecuteType::Command(timeouts.clone()))
+                .await?;
+        }
+        let result = match step.s_type {
+            StepType::InitSetup => self.setup_initial_version(executor).await,
+            StepType::Update => Ok(()),
+        };
+        let result = match result {
+            Ok(()) => run_statements(executor, step, &statements, &self.observers).await,
+            error => error,
+        };
+        if !timeouts.is_empty() {
+            let reset = executor
+                .execute(ExecuteType::Command(RESET_TIMEOUTS.to_string()))
+                .await;
+            // a failed transaction rejects the reset and reverts the settings on rollback
+            if result.is_ok() {
+                reset?;
+            }
+        }
+        result?;
+        Ok(())
+    }
+}
+
+/// runs the statements of a step one after another and reports each of them
+async fn run_statements(
+    executor: &mut dyn AsyncPostgresExecutor,
+    step: &Step,
+    statements: &[(Statement, DbAction)],
+    observers: &[Rc<dyn CortexObserver>],
+) -> Result<(), ConnectionError> {
+    for (index, (statement, action)) in statements.iter().enumerate() {
+        let started = Instant::now();
+        let mut commands = Vec::new();
+        execute_statement(executor, statement, action, &step.policy, &mut |command| {
+            commands.push(redact(command));
+            let command = render(command);
+            emit(
+                observers,
+                CortexEvent::StatementRendered {
+                    step,
+                    index,
+                    statements: statements.len(),
+                    command: &command,
+                },
+            );
+        })
+        .await?;
+        tracing::debug!(
+            object = %statement.name(),
+            action = action.kind(),
+            command = %commands.join("\n"),
+            duration_ms = started.elapsed().as_millis() as u64,
+            "statement executed"
+        );
+        emit(
+            observers,
+            CortexEvent::StatementExecuted {
+                step,
+                index,
+                statements: statements.len(),
+                duration: started.elapsed(),
+            },
+        );
+    }
+    Ok(())
+}
+
+/// runs a single statement, code statements receive the executor itself
+/// creates of existing objects are skipped if the policy allows it
+async fn execute_statement(
+    executor: &mut dyn AsyncPostgresExecutor,
+    statement: &Statement,
+    action: &DbAction,
+    policy: &ExistencePolicy,
+    on_command: &mut dyn FnMut(&ExecuteType),
+) -> Result<(), ConnectionError> {
+    match statement {
+        Statement::Code(code) => {
+            let run = code
+                .postgres_async
+                .as_ref()
+                .expect("code is validated before execution");
+            run(executor)
+                .await
+                .map_err(|e| ExecuteError(code.name.to_string(), e.to_string()).into())
+        }
+        statement => {
+            if let (ExistencePolicy::IfNotExists, DbAction::Create) = (policy, action) {
+                if let Some(query) = PostgresStatementProducer::exists_query(statement) {
+                    if !executor.query(query, &[]).await?.is_empty() {
+                        return Ok(());
+                    }
+                }
+            }
+            for command in PostgresStatementProducer::map(statement, action, policy) {
+                on_command(&command);
+                executor.execute(command).await?;
+            }
+            Ok(())
+        }
+    }
+}
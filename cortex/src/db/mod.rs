@@ -6,5 +6,4 @@ pub mod producer;
 pub mod prelude {
     pub use super::connection::prelude::*;
     pub use super::cortex::prelude::*;
-    pub use crate::objects::prelude::*;
 }
@@ -0,0 +1,167 @@
+use std::rc::Rc;
+
+#[cfg(feature = "mongodb")]
+use std::{future::Future, pin::Pin};
+
+#[cfg(feature = "mongodb")]
+use mongodb::{Client, ClientSession};
+
+#[cfg(feature = "postgres")]
+use crate::connection::postgres::PostgresExecutor;
+#[cfg(all(feature = "postgres", feature = "async"))]
+use crate::connection::postgres::{AsyncPostgresExecutor, AsyncPostgresFuture};
+use crate::objects::statement::Statement;
+
+/// Closure run on postgres, receives the open transaction if there is one
+/// otherwise the connection
+#[cfg(feature = "postgres")]
+pub type PostgresCode = dyn Fn(&mut dyn PostgresExecutor) -> anyhow::Result<()>;
+
+/// Closure run on postgres by the async orchestrator
+/// receives the open transaction if there is one otherwise the connection
+#[cfg(all(feature = "postgres", feature = "async"))]
+pub type AsyncPostgresCode = dyn for<'a> Fn(
+    &'a mut dyn AsyncPostgresExecutor,
+) -> AsyncPostgresFuture<'a, anyhow::Result<()>>;
+
+/// Future returned by a mongodb closure
+#[cfg(feature = "mongodb")]
+pub type MongoCodeFuture<'a> = Pin<Box<dyn Future<Output = anyhow::Result<()>> + 'a>>;
+
+/// Closure run on mongodb, receives the session of the open transaction if there is one
+#[cfg(feature = "mongodb")]
+pub type MongoCode =
+    dyn for<'a> Fn(&'a Client, Option<&'a mut ClientSession>) -> MongoCodeFuture<'a>;
+
+#[derive(Clone)]
+/// Code struct for running rust code as part of a step
+/// used for data migrations which can not be expressed with statements
+pub struct Code {
+    /// name of the code, used to identify it in errors
+    pub name: Rc<str>,
+    /// the closure run on postgres
+    #[cfg(feature = "postgres")]
+    pub postgres: Option<Rc<PostgresCode>>,
+    /// the closure run on postgres by the async orchestrator
+    #[cfg(all(feature = "postgres", feature = "async"))]
+    pub postgres_async: Option<Rc<AsyncPostgresCode>>,
+    /// the closure run on mongodb
+    #[cfg(feature = "mongodb")]
+    pub mongodb: Option<Rc<MongoCode>>,
+}
+
+impl Code {
+    /// Create a new code statement without any closures
+    /// # Example
+    /// ```
+    /// use cortex::objects::code::Code;
+    /// let code = Code::new("backfill_full_name");
+    /// assert_eq!(code.name, "backfill_full_name".into());
+    /// ```
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: Rc::from(name),
+            #[cfg(feature = "postgres")]
+            postgres: None,
+            #[cfg(all(feature = "postgres", feature = "async"))]
+            postgres_async: None,
+            #[cfg(feature = "mongodb")]
+            mongodb: None,
+        }
+    }
+
+    /// Set the closure that is run on postgres
+    /// # Example
+    /// ```
+    /// use cortex::connection::ExecuteType;
+    /// use cortex::objects::code::Code;
+    /// let code = Code::new("backfill_full_name").on_postgres(|db| {
+    ///     let rows = db.query(ExecuteType::Command("SELECT id, data FROM users".into()), &[])?;
+    ///     for row in rows {
+    ///         let id: i32 = row.get(0);
+    ///         db.execute(ExecuteType::Command(format!(
+    ///             "UPDATE users SET full_name = data->>'name' WHERE id = {}",
+    ///             id
+    ///         )))?;
+    ///     }
+    ///     Ok(())
+    /// });
+    /// assert!(code.postgres.is_some());
+    /// ```
+    #[cfg(feature = "postgres")]
+    pub fn on_postgres(
+        mut self,
+        code: impl Fn(&mut dyn PostgresExecutor) -> anyhow::Result<()> + 'static,
+    ) -> Self {
+        self.postgres = Some(Rc::new(code));
+        self
+    }
+
+    /// Set the closure that is run on postgres by the async orchestrator
+    /// # Example
+    /// ```
+    /// use cortex::connection::ExecuteType;
+    /// use cortex::objects::code::Code;
+    /// let code = Code::new("backfill_full_name").on_postgres_async(|db| {
+    ///     Box::pin(async move {
+    ///         db.execute(ExecuteType::Command(
+    ///             "UPDATE users SET full_name = data->>'name'".into(),
+    ///         ))
+    ///         .await?;
+    ///         Ok(())
+    ///     })
+    /// });
+    /// assert!(code.postgres_async.is_some());
+    /// ```
+    #[cfg(all(feature = "postgres", feature = "async"))]
+    pub fn on_postgres_async(
+        mut self,
+        code: impl for<'a> Fn(
+                &'a mut dyn AsyncPostgresExecutor,
+            ) -> AsyncPostgresFuture<'a, anyhow::Result<()>>
+            + 'static,
+    ) -> Self {
+        self.postgres_async = Some(Rc::new(code));
+        self
+    }
+
+    /// Set the closure that is run on mongodb
+    /// # Example
+    /// ```
+    /// use cortex::objects::code::Code;
+    /// use mongodb::bson::doc;
+    /// let code = Code::new("split_name").on_mongodb(|client, session| {
+    ///     Box::pin(async move {
+    ///         let users = client.database("default").collection::<mongodb::bson::Document>("users");
+    ///         let update = doc! { "$set": { "first_name": "" } };
+    ///         match session {
+    ///             Some(session) => users.update_many_with_session(doc! {}, update, None, session).await?,
+    ///             None => users.update_many(doc! {}, update, None).await?,
+    ///         };
+    ///         Ok(())
+    ///     })
+    /// });
+    /// assert!(code.mongodb.is_some());
+    /// ```
+    #[cfg(feature = "mongodb")]
+    pub fn on_mongodb(
+        mut self,
+        code: impl for<'a> Fn(&'a Client, Option<&'a mut ClientSession>) -> MongoCodeFuture<'a>
+            + 'static,
+    ) -> Self {
+        self.mongodb = Some(Rc::new(code));
+        self
+    }
+}
+
+impl From<Code> for Statement {
+    fn from(code: Code) -> Self {
+        Statement::Code(code)
+    }
+}
+
+impl From<&Code> for Statement {
+    fn from(code: &Code) -> Self {
+        Statement::Code(code.clone())
+    }
+}
@@ -0,0 +1,189 @@
+use std::rc::Rc;
+
+use crate::{
+    db::cortex::StepValidationError,
+    objects::{insert::Value, table::Table},
+};
+
+#[derive(Debug, Clone, Default)]
+/// Truncate struct for removing all rows of a table while keeping the table
+pub struct Truncate {
+    /// restart the sequences owned by the columns of the table
+    pub restart_identity: bool,
+    /// also truncate the tables referencing the table with foreign keys
+    pub cascade: bool,
+}
+
+impl Truncate {
+    /// Create a new truncate without options
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restart the sequences owned by the columns of the table, postgres only
+    /// # Example
+    /// ```
+    /// use cortex::objects::delete::Truncate;
+    /// let truncate = Truncate::new().restart_identity().cascade();
+    /// assert!(truncate.restart_identity);
+    /// assert!(truncate.cascade);
+    /// ```
+    pub fn restart_identity(mut self) -> Self {
+        self.restart_identity = true;
+        self
+    }
+
+    /// Also truncate the tables referencing the table, postgres only
+    pub fn cascade(mut self) -> Self {
+        self.cascade = true;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Filter enum for selecting the rows a delete removes
+/// values are sent as bind parameters
+pub enum Filter {
+    Eq(Rc<str>, Value),
+    Ne(Rc<str>, Value),
+    Lt(Rc<str>, Value),
+    Lte(Rc<str>, Value),
+    Gt(Rc<str>, Value),
+    Gte(Rc<str>, Value),
+    In(Rc<str>, Vec<Value>),
+    IsNull(Rc<str>),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    /// Rows where the column equals the value
+    /// # Example
+    /// ```
+    /// use cortex::objects::delete::Filter;
+    /// let filter = Filter::eq("code", "xx").or(Filter::lt("id", 0));
+    /// assert!(matches!(filter, Filter::Or(filters) if filters.len() == 2));
+    /// ```
+    pub fn eq(column: &str, value: impl Into<Value>) -> Self {
+        Filter::Eq(Rc::from(column), value.into())
+    }
+
+    /// Rows where the column does not equal the value
+    pub fn ne(column: &str, value: impl Into<Value>) -> Self {
+        Filter::Ne(Rc::from(column), value.into())
+    }
+
+    /// Rows where the column is less than the value
+    pub fn lt(column: &str, value: impl Into<Value>) -> Self {
+        Filter::Lt(Rc::from(column), value.into())
+    }
+
+    /// Rows where the column is less than or equal to the value
+    pub fn lte(column: &str, value: impl Into<Value>) -> Self {
+        Filter::Lte(Rc::from(column), value.into())
+    }
+
+    /// Rows where the column is greater than the value
+    pub fn gt(column: &str, value: impl Into<Value>) -> Self {
+        Filter::Gt(Rc::from(column), value.into())
+    }
+
+    /// Rows where the column is greater than or equal to the value
+    pub fn gte(column: &str, value: impl Into<Value>) -> Self {
+        Filter::Gte(Rc::from(column), value.into())
+    }
+
+    /// Rows where the column equals one of the values
+    pub fn is_in(column: &str, values: Vec<impl Into<Value>>) -> Self {
+        Filter::In(
+            Rc::from(column),
+            values.into_iter().map(Into::into).collect(),
+        )
+    }
+
+    /// Rows where the column is null
+    pub fn is_null(column: &str) -> Self {
+        Filter::IsNull(Rc::from(column))
+    }
+
+    /// Rows matching this and the other filter
+    pub fn and(self, other: Filter) -> Self {
+        match self {
+            Filter::And(mut filters) => {
+                filters.push(other);
+                Filter::And(filters)
+            }
+            filter => Filter::And(vec![filter, other]),
+        }
+    }
+
+    /// Rows matching this or the other filter
+    pub fn or(self, other: Filter) -> Self {
+        match self {
+            Filter::Or(mut filters) => {
+                filters.push(other);
+                Filter::Or(filters)
+            }
+            filter => Filter::Or(vec![filter, other]),
+        }
+    }
+
+    /// Rows not matching this filter
+    pub fn negate(self) -> Self {
+        Filter::Not(Box::new(self))
+    }
+
+    /// Checks the columns and values of the filter against the props of the table
+    /// # Example
+    /// ```
+    /// use cortex::objects::delete::Filter;
+    /// use cortex::objects::table::{PropType, Table};
+    /// let table = Table::new("countries").add_prop(("code", PropType::Text, None));
+    /// assert!(Filter::eq("code", "xx").validate(&table).is_ok());
+    /// assert!(Filter::eq("code", 1).validate(&table).is_err());
+    /// assert!(Filter::eq("name", "xx").validate(&table).is_err());
+    /// ```
+    pub fn validate(&self, table: &Table) -> Result<(), StepValidationError> {
+        let check = |column: &Rc<str>, values: &[&Value]| {
+            let prop = table
+                .props
+                .iter()
+                .find(|p| p.name == *column)
+                .ok_or_else(|| {
+                    StepValidationError(format!(
+                        "column {} does not exist on table {}",
+                        column, table.name
+                    ))
+                })?;
+            match values.iter().find(|v| !v.matches(&prop.t_type)) {
+                Some(value) => Err(StepValidationError(format!(
+                    "value {:?} does not match type {:?} of column {}.{}",
+                    value, prop.t_type, table.name, column
+                ))),
+                None => Ok(()),
+            }
+        };
+        match self {
+            Filter::Eq(column, value)
+            | Filter::Ne(column, value)
+            | Filter::Lt(column, value)
+            | Filter::Lte(column, value)
+            | Filter::Gt(column, value)
+            | Filter::Gte(column, value) => check(column, &[value]),
+            Filter::In(column, values) if values.is_empty() => Err(StepValidationError(format!(
+                "filter on {}.{} has no values",
+                table.name, column
+            ))),
+            Filter::In(column, values) => check(column, &values.iter().collect::<Vec<&Value>>()),
+            Filter::IsNull(column) => check(column, &[]),
+            Filter::And(filters) | Filter::Or(filters) if filters.is_empty() => Err(
+                StepValidationError(format!("filter on {} has no conditions", table.name)),
+            ),
+            Filter::And(filters) | Filter::Or(filters) => {
+                filters.iter().try_for_each(|f| f.validate(table))
+            }
+            Filter::Not(filter) => filter.validate(table),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use std::rc::Rc;
+
+use crate::objects::statement::Statement;
+
+#[derive(Debug, Clone)]
+/// Function struct for creating functions without parameters
+/// mostly used as the function of a trigger
+pub struct Function {
+    /// name of the function
+    pub name: Rc<str>,
+    /// type the function returns
+    pub returns: Rc<str>,
+    /// language the body is written in
+    pub language: Rc<str>,
+    /// body of the function
+    pub body: Rc<str>,
+}
+
+impl Function {
+    /// Create a new trigger function written in plpgsql
+    /// # Example
+    /// ```
+    /// use cortex::objects::function::Function;
+    /// let function = Function::new("touch", "BEGIN NEW.updated_at = now(); RETURN NEW; END;");
+    /// assert_eq!(function.returns, "trigger".into());
+    /// assert_eq!(function.language, "plpgsql".into());
+    /// ```
+    pub fn new(name: &str, body: &str) -> Self {
+        Self {
+            name: Rc::from(name),
+            returns: Rc::from("trigger"),
+            language: Rc::from("plpgsql"),
+            body: Rc::from(body),
+        }
+    }
+
+    /// Set the type the function returns
+    pub fn returns(mut self, returns: &str) -> Self {
+        self.returns = Rc::from(returns);
+        self
+    }
+
+    /// Set the language the body is written in
+    /// # Example
+    /// ```
+    /// use cortex::objects::function::Function;
+    /// let function = Function::new("answer", "SELECT 42").returns("INT").language("sql");
+    /// assert_eq!(function.language, "sql".into());
+    /// ```
+    pub fn language(mut self, language: &str) -> Self {
+        self.language = Rc::from(language);
+        self
+    }
+}
+
+impl From<Function> for Statement {
+    fn from(function: Function) -> Self {
+        Statement::Function(function)
+    }
+}
+
+impl From<&Function> for Statement {
+    fn from(function: &Function) -> Self {
+        Statement::Function(function.clone())
+    }
+}
+
+impl From<&Function> for Rc<str> {
+    fn from(function: &Function) -> Self {
+        function.name.clone()
+    }
+}
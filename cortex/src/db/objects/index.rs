@@ -0,0 +1,93 @@
+use std::rc::Rc;
+
+use crate::objects::statement::Statement;
+
+#[derive(Debug, Clone)]
+/// Index struct for creating indexes on a table
+pub struct Index {
+    /// name of the index
+    pub name: Rc<str>,
+    /// table the index is created on
+    pub table: Rc<str>,
+    /// columns of the table the index covers
+    pub columns: Vec<Rc<str>>,
+    /// whether the index enforces unique values
+    pub unique: bool,
+    /// build and drop the index without locking writes to the table
+    /// cannot run inside a transaction on postgres
+    pub concurrently: bool,
+}
+
+impl Index {
+    /// Create a new index on the given table
+    /// # Example
+    /// ```
+    /// use cortex::objects::index::Index;
+    /// use cortex::objects::table::Table;
+    /// let users = Table::new("users");
+    /// let index = Index::new("users_id_idx", &users);
+    /// assert_eq!(index.name, "users_id_idx".into());
+    /// assert_eq!(index.table, "users".into());
+    /// ```
+    pub fn new(name: &str, table: impl Into<Rc<str>>) -> Self {
+        Self {
+            name: Rc::from(name),
+            table: table.into(),
+            columns: Vec::new(),
+            unique: false,
+            concurrently: false,
+        }
+    }
+
+    /// Add a column to the index
+    /// # Example
+    /// ```
+    /// use cortex::objects::index::Index;
+    /// let index = Index::new("users_id_idx", "users")
+    ///    .add_column("id")
+    ///    .add_column("name");
+    /// assert_eq!(index.columns.len(), 2);
+    /// assert_eq!(index.columns[0], "id".into());
+    /// ```
+    pub fn add_column(mut self, column: &str) -> Self {
+        self.columns.push(Rc::from(column));
+        self
+    }
+
+    /// Mark the index as unique
+    /// # Example
+    /// ```
+    /// use cortex::objects::index::Index;
+    /// let index = Index::new("users_id_idx", "users").unique();
+    /// assert!(index.unique);
+    /// ```
+    pub fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+
+    /// Build and drop the index without locking writes to the table
+    /// the step of the index has to run without a transaction on postgres
+    /// # Example
+    /// ```
+    /// use cortex::objects::index::Index;
+    /// let index = Index::new("users_id_idx", "users").concurrently();
+    /// assert!(index.concurrently);
+    /// ```
+    pub fn concurrently(mut self) -> Self {
+        self.concurrently = true;
+        self
+    }
+}
+
+impl From<Index> for Statement {
+    fn from(index: Index) -> Self {
+        Statement::Index(index)
+    }
+}
+
+impl From<&Index> for Statement {
+    fn from(index: &Index) -> Self {
+        Statement::Index(index.clone())
+    }
+}
@@ -0,0 +1,287 @@
+use std::rc::Rc;
+
+use crate::{
+    db::cortex::StepValidationError,
+    objects::table::{PropAnnotation, PropType, Table},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+/// Value enum for typed values of a row
+/// Date and Timestamp are passed as ISO 8601 strings
+pub enum Value {
+    Int(i32),
+    SmallInt(i16),
+    BigInt(i64),
+    Double(f64),
+    Text(String),
+    Bool(bool),
+    Date(String),
+    Timestamp(String),
+    Null,
+}
+
+impl Value {
+    /// checks if the value can be stored in a property of the given type
+    pub fn matches(&self, prop_type: &PropType) -> bool {
+        matches!(
+            (self, prop_type),
+            (Value::Null, _)
+                | (Value::Int(_), PropType::Int)
+                | (Value::SmallInt(_), PropType::SmallInt)
+                | (Value::BigInt(_), PropType::BigInt)
+                | (Value::Double(_), PropType::Double)
+                | (Value::Text(_), PropType::Text)
+                | (Value::Bool(_), PropType::Bool)
+                | (Value::Date(_), PropType::Date)
+                | (Value::Timestamp(_), PropType::Timestamp)
+        )
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::Int(value)
+    }
+}
+
+impl From<i16> for Value {
+    fn from(value: i16) -> Self {
+        Value::SmallInt(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::BigInt(value)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Double(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Text(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::Text(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Value::Null)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Row struct holding the values of a single record by column name
+pub struct Row {
+    pub values: Vec<(Rc<str>, Value)>,
+}
+
+impl Row {
+    /// Create a new empty row
+    pub fn new() -> Self {
+        Self { values: Vec::new() }
+    }
+
+    /// Set the value of a column
+    /// # Example
+    /// ```
+    /// use cortex::objects::insert::{Row, Value};
+    /// let row = Row::new().set("id", 1).set("name", "admin");
+    /// assert_eq!(row.values.len(), 2);
+    /// assert_eq!(row.get("name"), Some(&Value::Text("admin".into())));
+    /// ```
+    pub fn set(mut self, column: &str, value: impl Into<Value>) -> Self {
+        self.values.push((Rc::from(column), value.into()));
+        self
+    }
+
+    /// Get the value of a column
+    pub fn get(&self, column: &str) -> Option<&Value> {
+        self.values
+            .iter()
+            .find(|(name, _)| name.as_ref() == column)
+            .map(|(_, value)| value)
+    }
+
+    /// names of the columns set in this row
+    pub fn columns(&self) -> Vec<Rc<str>> {
+        self.values.iter().map(|(name, _)| name.clone()).collect()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// OnConflict enum for defining what happens if a row already exists
+pub enum OnConflict {
+    /// the insert fails
+    #[default]
+    Fail,
+    /// the row is skipped, matched on the given columns
+    /// or any unique constraint if empty
+    DoNothing(Vec<Rc<str>>),
+    /// the row is updated, matched on the given columns
+    DoUpdate(Vec<Rc<str>>),
+}
+
+#[derive(Debug, Clone, Default)]
+/// Insert struct for seeding rows into a table
+pub struct Insert {
+    /// the rows that are inserted
+    pub rows: Vec<Row>,
+    /// what happens if a row already exists
+    pub on_conflict: OnConflict,
+}
+
+impl Insert {
+    /// Create a new insert without rows
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a row to the insert
+    /// # Example
+    /// ```
+    /// use cortex::objects::insert::{Insert, Row};
+    /// let insert = Insert::new()
+    ///    .add_row(Row::new().set("id", 1).set("code", "de"))
+    ///    .add_row(Row::new().set("id", 2).set("code", "us"));
+    /// assert_eq!(insert.rows.len(), 2);
+    /// ```
+    pub fn add_row(mut self, row: Row) -> Self {
+        self.rows.push(row);
+        self
+    }
+
+    /// Add multiple rows to the insert
+    pub fn add_rows(mut self, rows: Vec<Row>) -> Self {
+        self.rows.extend(rows);
+        self
+    }
+
+    /// Set what happens if a row already exists
+    /// # Example
+    /// ```
+    /// use cortex::objects::insert::{Insert, OnConflict, Row};
+    /// let insert = Insert::new()
+    ///    .add_row(Row::new().set("id", 1).set("code", "de"))
+    ///    .on_conflict(OnConflict::DoUpdate(vec!["id".into()]));
+    /// ```
+    pub fn on_conflict(mut self, on_conflict: OnConflict) -> Self {
+        self.on_conflict = on_conflict;
+        self
+    }
+
+    /// the columns of the insert in the order of the first row
+    pub fn columns(&self) -> Vec<Rc<str>> {
+        self.rows.first().map(Row::columns).unwrap_or_default()
+    }
+
+    /// the columns that identify an existing row
+    pub fn conflict_columns(&self) -> &[Rc<str>] {
+        match &self.on_conflict {
+            OnConflict::Fail => &[],
+            OnConflict::DoNothing(columns) | OnConflict::DoUpdate(columns) => columns,
+        }
+    }
+
+    /// Checks the rows against the props of the table
+    /// # Example
+    /// ```
+    /// use cortex::objects::insert::{Insert, Row};
+    /// use cortex::objects::table::{PropType, Table};
+    /// let table = Table::new("countries").add_prop(("id", PropType::Int, None));
+    /// assert!(Insert::new().add_row(Row::new().set("id", 1)).validate(&table).is_ok());
+    /// assert!(Insert::new().add_row(Row::new().set("id", "de")).validate(&table).is_err());
+    /// assert!(Insert::new().add_row(Row::new()).validate(&table).is_err());
+    /// ```
+    pub fn validate(&self, table: &Table) -> Result<(), StepValidationError> {
+        if self.rows.is_empty() {
+            return Err(StepValidationError(format!(
+                "insert into {} has no rows",
+                table.name
+            )));
+        }
+        let columns = self.columns();
+        if columns.is_empty() {
+            return Err(StepValidationError(format!(
+                "rows inserted into {} have no columns",
+                table.name
+            )));
+        }
+        for row in &self.rows {
+            let mut row_columns = row.columns();
+            let mut expected = columns.clone();
+            row_columns.sort();
+            expected.sort();
+            if row_columns != expected {
+                return Err(StepValidationError(format!(
+                    "all rows inserted into {} need the same columns {:?}",
+                    table.name, columns
+                )));
+            }
+            for (column, value) in &row.values {
+                let prop = table
+                    .props
+                    .iter()
+                    .find(|p| p.name == *column)
+                    .ok_or_else(|| {
+                        StepValidationError(format!(
+                            "column {} does not exist on table {}",
+                            column, table.name
+                        ))
+                    })?;
+                if !value.matches(&prop.t_type) {
+                    return Err(StepValidationError(format!(
+                        "value {:?} does not match type {:?} of column {}.{}",
+                        value, prop.t_type, table.name, column
+                    )));
+                }
+                if *value == Value::Null
+                    && matches!(
+                        prop.annotation,
+                        Some(PropAnnotation::NotNull) | Some(PropAnnotation::PrimaryKey)
+                    )
+                {
+                    return Err(StepValidationError(format!(
+                        "column {}.{} can not be null",
+                        table.name, column
+                    )));
+                }
+            }
+        }
+        if let Some(column) = self
+            .conflict_columns()
+            .iter()
+            .find(|c| !columns.contains(c))
+        {
+            return Err(StepValidationError(format!(
+                "conflict column {} is not part of the insert into {}",
+                column, table.name
+            )));
+        }
+        if let OnConflict::DoUpdate(target) = &self.on_conflict {
+            if target.is_empty() {
+                return Err(StepValidationError(format!(
+                    "updating conflicting rows of {} requires conflict columns",
+                    table.name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,18 +1,38 @@
+pub mod code;
 pub mod database;
+pub mod delete;
+pub mod function;
+pub mod index;
+pub mod insert;
+pub mod namespace;
 pub mod procedure;
+pub mod raw;
 pub mod role;
+pub mod schema;
 pub mod sequence;
 pub mod statement;
 pub mod step;
 pub mod table;
+pub mod trigger;
 pub mod user;
+pub mod view;
 
 pub mod prelude {
+    pub use super::code::Code;
     pub use super::database::Database;
+    pub use super::delete::{Filter, Truncate};
+    pub use super::function::Function;
+    pub use super::index::Index;
+    pub use super::insert::{Insert, OnConflict, Row, Value};
+    pub use super::namespace::Namespace;
+    pub use super::raw::Raw;
     pub use super::role::Role;
+    pub use super::schema::Schema;
     pub use super::sequence::Sequence;
-    pub use super::statement::{DbAction, Statement};
-    pub use super::step::{Step, StepType};
-    pub use super::table::{PropType, Table, TableProp};
+    pub use super::statement::{Alter, ChangeSafety, DbAction, ExistencePolicy, Statement};
+    pub use super::step::{Step, StepTransaction, StepType};
+    pub use super::table::{PropAnnotation, PropType, Table, TableProp};
+    pub use super::trigger::{Trigger, TriggerEvent, TriggerTiming};
     pub use super::user::User;
+    pub use super::view::View;
 }
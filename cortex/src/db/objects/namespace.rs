@@ -0,0 +1,38 @@
+use std::rc::Rc;
+
+use crate::objects::statement::Statement;
+
+#[doc(alias = "Schema")]
+#[derive(Debug, Clone)]
+/// Namespace inside of a database which groups tables, views and other objects
+pub struct Namespace {
+    /// name of the namespace
+    pub name: Rc<str>,
+}
+
+impl Namespace {
+    /// Create a new namespace with the given name.
+    /// # Example
+    /// ```
+    /// use cortex::objects::namespace::Namespace;
+    /// let namespace = Namespace::new("billing");
+    /// assert_eq!(namespace.name, "billing".into());
+    /// ```
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: Rc::from(name),
+        }
+    }
+}
+
+impl From<Namespace> for Statement {
+    fn from(namespace: Namespace) -> Self {
+        Statement::Namespace(namespace)
+    }
+}
+
+impl From<&Namespace> for Statement {
+    fn from(namespace: &Namespace) -> Self {
+        Statement::Namespace(namespace.clone())
+    }
+}
@@ -0,0 +1,92 @@
+use std::rc::Rc;
+
+#[cfg(feature = "mongodb")]
+use mongodb::bson::Document;
+
+use crate::objects::statement::Statement;
+
+#[derive(Debug, Clone)]
+/// Raw struct for backend specific statements cortex does not model yet
+/// every backend picks its own variant, a step fails validation if its backend has none
+pub struct Raw {
+    /// name of the statement, used to identify it in errors
+    pub name: Rc<str>,
+    /// sql run on postgres
+    pub postgres: Option<Rc<str>>,
+    /// sql run on sqlite
+    pub sqlite: Option<Rc<str>>,
+    /// database and command document run on mongodb
+    #[cfg(feature = "mongodb")]
+    pub mongodb: Option<(Rc<str>, Document)>,
+}
+
+impl Raw {
+    /// Create a new raw statement without any variants
+    /// # Example
+    /// ```
+    /// use cortex::objects::raw::Raw;
+    /// let raw = Raw::new("enable_trgm");
+    /// assert_eq!(raw.name, "enable_trgm".into());
+    /// ```
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: Rc::from(name),
+            postgres: None,
+            sqlite: None,
+            #[cfg(feature = "mongodb")]
+            mongodb: None,
+        }
+    }
+
+    /// Set the sql run on postgres
+    /// # Example
+    /// ```
+    /// use cortex::objects::raw::Raw;
+    /// let raw = Raw::new("enable_trgm").on_postgres("CREATE EXTENSION IF NOT EXISTS pg_trgm;");
+    /// assert!(raw.postgres.is_some());
+    /// ```
+    pub fn on_postgres(mut self, sql: &str) -> Self {
+        self.postgres = Some(Rc::from(sql));
+        self
+    }
+
+    /// Set the sql run on sqlite
+    pub fn on_sqlite(mut self, sql: &str) -> Self {
+        self.sqlite = Some(Rc::from(sql));
+        self
+    }
+
+    /// Set the command document run on the given mongodb database
+    /// # Example
+    /// ```
+    /// use cortex::objects::raw::Raw;
+    /// use mongodb::bson::doc;
+    /// let raw = Raw::new("ttl_index")
+    ///    .on_postgres("CREATE INDEX sessions_created_idx ON sessions (created);")
+    ///    .on_mongodb(
+    ///        "default",
+    ///        doc! {
+    ///            "createIndexes": "sessions",
+    ///            "indexes": [{ "key": { "created": 1 }, "name": "created_ttl", "expireAfterSeconds": 3600 }],
+    ///        },
+    ///    );
+    /// assert!(raw.mongodb.is_some());
+    /// ```
+    #[cfg(feature = "mongodb")]
+    pub fn on_mongodb(mut self, database: impl Into<Rc<str>>, command: Document) -> Self {
+        self.mongodb = Some((database.into(), command));
+        self
+    }
+}
+
+impl From<Raw> for Statement {
+    fn from(raw: Raw) -> Self {
+        Statement::Raw(raw)
+    }
+}
+
+impl From<&Raw> for Statement {
+    fn from(raw: &Raw) -> Self {
+        Statement::Raw(raw.clone())
+    }
+}
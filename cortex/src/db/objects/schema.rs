@@ -0,0 +1,420 @@
+use std::{collections::BTreeMap, rc::Rc};
+
+use crate::{
+    db::cortex::StepValidationError,
+    objects::{
+        database::Database,
+        function::Function,
+        index::Index,
+        namespace::Namespace,
+        sequence::Sequence,
+        statement::{Alter, DbAction, ExistencePolicy, Statement},
+        step::Step,
+        table::Table,
+        trigger::Trigger,
+        view::View,
+    },
+};
+
+#[derive(Debug, Clone)]
+/// In memory catalog of the objects created by replaying steps
+/// no database is touched, which makes it possible to inspect the schema
+/// at any version and to catch invalid statements before they are sent
+pub struct Schema {
+    /// version of the last applied step
+    pub version: semver::Version,
+    pub databases: BTreeMap<Rc<str>, Database>,
+    pub tables: BTreeMap<Rc<str>, Table>,
+    pub indexes: BTreeMap<Rc<str>, Index>,
+    pub sequences: BTreeMap<Rc<str>, Sequence>,
+    pub views: BTreeMap<Rc<str>, View>,
+    pub namespaces: BTreeMap<Rc<str>, Namespace>,
+    pub functions: BTreeMap<Rc<str>, Function>,
+    pub triggers: BTreeMap<Rc<str>, Trigger>,
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Self {
+            version: semver::Version::new(0, 0, 0),
+            databases: BTreeMap::new(),
+            tables: BTreeMap::new(),
+            indexes: BTreeMap::new(),
+            sequences: BTreeMap::new(),
+            views: BTreeMap::new(),
+            namespaces: BTreeMap::new(),
+            functions: BTreeMap::new(),
+            triggers: BTreeMap::new(),
+        }
+    }
+}
+
+impl Schema {
+    /// Create a new empty schema
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replay all steps in version order
+    /// # Example
+    /// ```
+    /// use cortex::objects::schema::Schema;
+    /// use cortex::objects::step::{Step, StepType};
+    /// use cortex::objects::statement::DbAction;
+    /// use cortex::objects::table::{PropType, Table};
+    ///
+    /// let users = Table::new("users").add_prop(("id", PropType::Int, None));
+    /// let create = Step::new("create", StepType::Update, semver::Version::new(0, 0, 1))
+    ///    .add_statement(&users, DbAction::Create);
+    /// let rename = Step::new("rename", StepType::Update, semver::Version::new(0, 0, 2))
+    ///    .add_statement(&users, DbAction::Rename("customers".into()));
+    /// let schema = Schema::from_steps(&[create, rename]).unwrap();
+    /// assert!(schema.table("users").is_none());
+    /// assert_eq!(schema.table("customers").unwrap().props.len(), 1);
+    /// ```
+    pub fn from_steps(steps: &[Step]) -> Result<Self, StepValidationError> {
+        let mut schema = Self::new();
+        let mut steps = steps.iter().collect::<Vec<&Step>>();
+        steps.sort_by(|a, b| a.version.cmp(&b.version));
+        for step in steps {
+            schema.apply_step(step)?;
+        }
+        Ok(schema)
+    }
+
+    /// Replay all steps up to and including the given version
+    /// # Example
+    /// ```
+    /// use cortex::objects::schema::Schema;
+    /// use cortex::objects::step::{Step, StepType};
+    /// use cortex::objects::statement::DbAction;
+    /// use cortex::objects::table::Table;
+    ///
+    /// let users = Table::new("users");
+    /// let steps = vec![
+    ///     Step::new("create", StepType::Update, semver::Version::new(0, 0, 1))
+    ///        .add_statement(&users, DbAction::Create),
+    ///     Step::new("drop", StepType::Update, semver::Version::new(0, 0, 2))
+    ///        .add_statement(&users, DbAction::Drop),
+    /// ];
+    /// let schema = Schema::at_version(&steps, &semver::Version::new(0, 0, 1)).unwrap();
+    /// assert!(schema.table("users").is_some());
+    /// ```
+    pub fn at_version(
+        steps: &[Step],
+        version: &semver::Version,
+    ) -> Result<Self, StepValidationError> {
+        let steps = steps
+            .iter()
+            .filter(|step| step.version <= *version)
+            .cloned()
+            .collect::<Vec<Step>>();
+        Self::from_steps(&steps)
+    }
+
+    /// checks if the object of the statement is part of the schema
+    /// code and raw statements are never part of it
+    pub fn contains(&self, statement: &Statement) -> bool {
+        let name = statement.name();
+        match statement {
+            Statement::Table(_) => self.tables.contains_key(&name),
+            Statement::Database(_) => self.databases.contains_key(&name),
+            Statement::Index(_) => self.indexes.contains_key(&name),
+            Statement::Sequence(_) => self.sequences.contains_key(&name),
+            Statement::View(_) => self.views.contains_key(&name),
+            Statement::Namespace(_) => self.namespaces.contains_key(&name),
+            Statement::Function(_) => self.functions.contains_key(&name),
+            Statement::Trigger(_) => self.triggers.contains_key(&name),
+            Statement::Code(_) | Statement::Raw(_) => false,
+        }
+    }
+
+    /// Get a table by name
+    pub fn table(&self, name: &str) -> Option<&Table> {
+        self.tables.get(name)
+    }
+
+    /// Apply all statements of a step honouring its [`ExistencePolicy`]
+    /// drops of objects that were never created fail under every policy
+    /// even though the rendered statement tolerates them
+    /// # Example
+    /// ```
+    /// use cortex::objects::schema::Schema;
+    /// use cortex::objects::step::{Step, StepType};
+    /// use cortex::objects::statement::DbAction;
+    /// use cortex::objects::table::Table;
+    ///
+    /// let drop = Step::new("drop", StepType::Update, semver::Version::new(0, 0, 1))
+    ///    .add_statement(&Table::new("users"), DbAction::Drop);
+    /// assert!(Schema::new().apply_step(&drop).is_err());
+    /// ```
+    pub fn apply_step(&mut self, step: &Step) -> Result<(), StepValidationError> {
+        for (statement, action) in &step.ordered_statements()? {
+            let exists = self.contains(statement);
+            match (&step.policy, action) {
+                (ExistencePolicy::Strict, _) => self.apply(statement, action),
+                (ExistencePolicy::IfNotExists, DbAction::Create) if exists => Ok(()),
+                (ExistencePolicy::CreateOrReplace, DbAction::Create) if exists => self
+                    .apply(statement, &DbAction::Drop)
+                    .and_then(|_| self.apply(statement, action)),
+                _ => self.apply(statement, action),
+            }
+            .map_err(|e| {
+                StepValidationError(format!("step {} {}: {}", step.name, step.version, e.0))
+            })?;
+        }
+        self.version = step.version.clone();
+        Ok(())
+    }
+
+    /// Apply a single statement
+    /// # Example
+    /// ```
+    /// use cortex::objects::schema::Schema;
+    /// use cortex::objects::statement::{DbAction, Statement};
+    /// use cortex::objects::table::Table;
+    ///
+    /// let mut schema = Schema::new();
+    /// let users = Statement::from(Table::new("users"));
+    /// assert!(schema.apply(&users, &DbAction::Drop).is_err());
+    /// ```
+    pub fn apply(
+        &mut self,
+        statement: &Statement,
+        action: &DbAction,
+    ) -> Result<(), StepValidationError> {
+        match statement {
+            Statement::Table(table) => self.apply_table(table, action),
+            Statement::Database(database) => apply_object(
+                &mut self.databases,
+                "database",
+                database,
+                action,
+                |d, name| d.name = name,
+            ),
+            Statement::Index(index) => {
+                if matches!(action, DbAction::Create) && !self.tables.contains_key(&index.table) {
+                    return Err(StepValidationError(format!(
+                        "index {} is created on table {} which does not exist",
+                        index.name, index.table
+                    )));
+                }
+                apply_object(&mut self.indexes, "index", index, action, |i, name| {
+                    i.name = name
+                })
+            }
+            Statement::Sequence(sequence) => apply_object(
+                &mut self.sequences,
+                "sequence",
+                sequence,
+                action,
+                |s, name| s.name = name,
+            ),
+            Statement::View(view) => {
+                apply_object(&mut self.views, "view", view, action, |v, name| {
+                    v.name = name
+                })
+            }
+            Statement::Namespace(namespace) => apply_object(
+                &mut self.namespaces,
+                "schema",
+                namespace,
+                action,
+                |n, name| n.name = name,
+            ),
+            Statement::Function(function) => apply_object(
+                &mut self.functions,
+                "function",
+                function,
+                action,
+                |f, name| f.name = name,
+            ),
+            Statement::Trigger(trigger) => {
+                if matches!(action, DbAction::Create) {
+                    if !self.tables.contains_key(&trigger.table) {
+                        return Err(StepValidationError(format!(
+                            "trigger {} is created on table {} which does not exist",
+                            trigger.name, trigger.table
+                        )));
+                    }
+                    if !self.functions.contains_key(&trigger.function) {
+                        return Err(StepValidationError(format!(
+                            "trigger {} runs function {} which does not exist",
+                            trigger.name, trigger.function
+                        )));
+                    }
+                }
+                apply_object(&mut self.triggers, "trigger", trigger, action, |t, name| {
+                    t.name = name
+                })
+            }
+            // code and raw statements are opaque to the schema
+            Statement::Code(_) | Statement::Raw(_) => Ok(()),
+        }
+    }
+
+    fn apply_table(&mut self, table: &Table, action: &DbAction) -> Result<(), StepValidationError> {
+        match action {
+            DbAction::Insert(insert) => insert.validate(self.existing_table(&table.name)?),
+            DbAction::Delete(filter) => filter.validate(self.existing_table(&table.name)?),
+            DbAction::RenameColumn(from, to) => {
+                let existing = self.existing_table_mut(&table.name)?;
+                if existing.props.iter().any(|p| p.name == *to) {
+                    return Err(StepValidationError(format!(
+                        "column {}.{} already exists",
+                        table.name, to
+                    )));
+                }
+                let prop = existing
+                    .props
+                    .iter_mut()
+                    .find(|p| p.name == *from)
+                    .ok_or_else(|| {
+                        StepValidationError(format!(
+                            "column {}.{} does not exist",
+                            table.name, from
+                        ))
+                    })?;
+                prop.name = to.clone();
+                Ok(())
+            }
+            DbAction::Alter(Alter::AddColumn(prop)) => {
+                let existing = self.existing_table_mut(&table.name)?;
+                if existing.props.iter().any(|p| p.name == prop.name) {
+                    return Err(StepValidationError(format!(
+                        "column {}.{} already exists",
+                        table.name, prop.name
+                    )));
+                }
+                existing.props.push(prop.clone());
+                Ok(())
+            }
+            DbAction::Alter(Alter::DropColumn(column)) => {
+                let existing = self.existing_table_mut(&table.name)?;
+                if !existing.props.iter().any(|p| p.name == *column) {
+                    return Err(StepValidationError(format!(
+                        "column {}.{} does not exist",
+                        table.name, column
+                    )));
+                }
+                existing.props.retain(|p| p.name != *column);
+                Ok(())
+            }
+            DbAction::Alter(Alter::ColumnType(column, prop_type)) => {
+                let existing = self.existing_table_mut(&table.name)?;
+                let prop = existing
+                    .props
+                    .iter_mut()
+                    .find(|p| p.name == *column)
+                    .ok_or_else(|| {
+                        StepValidationError(format!(
+                            "column {}.{} does not exist",
+                            table.name, column
+                        ))
+                    })?;
+                prop.t_type = prop_type.clone();
+                Ok(())
+            }
+            DbAction::Rename(name) => {
+                apply_object(&mut self.tables, "table", table, action, |t, name| {
+                    t.name = name
+                })?;
+                self.indexes
+                    .values_mut()
+                    .filter(|i| i.table == table.name)
+                    .for_each(|i| i.table = name.clone());
+                self.triggers
+                    .values_mut()
+                    .filter(|t| t.table == table.name)
+                    .for_each(|t| t.table = name.clone());
+                Ok(())
+            }
+            DbAction::Drop => {
+                apply_object(&mut self.tables, "table", table, action, |t, name| {
+                    t.name = name
+                })?;
+                self.indexes.retain(|_, i| i.table != table.name);
+                self.triggers.retain(|_, t| t.table != table.name);
+                Ok(())
+            }
+            _ => apply_object(&mut self.tables, "table", table, action, |t, name| {
+                t.name = name
+            }),
+        }
+    }
+
+    fn existing_table(&self, name: &Rc<str>) -> Result<&Table, StepValidationError> {
+        self.tables
+            .get(name)
+            .ok_or_else(|| StepValidationError(format!("table {} does not exist", name)))
+    }
+
+    fn existing_table_mut(&mut self, name: &Rc<str>) -> Result<&mut Table, StepValidationError> {
+        self.tables
+            .get_mut(name)
+            .ok_or_else(|| StepValidationError(format!("table {} does not exist", name)))
+    }
+}
+
+/// applies create, drop and rename to a catalog of objects keyed by name
+fn apply_object<T: Clone + HasName>(
+    objects: &mut BTreeMap<Rc<str>, T>,
+    kind: &str,
+    object: &T,
+    action: &DbAction,
+    set_name: impl Fn(&mut T, Rc<str>),
+) -> Result<(), StepValidationError> {
+    let name = object.name();
+    match action {
+        DbAction::Create => {
+            if objects.contains_key(&name) {
+                return Err(StepValidationError(format!(
+                    "{} {} already exists",
+                    kind, name
+                )));
+            }
+            objects.insert(name, object.clone());
+            Ok(())
+        }
+        DbAction::Drop => objects
+            .remove(&name)
+            .map(|_| ())
+            .ok_or_else(|| StepValidationError(format!("{} {} does not exist", kind, name))),
+        DbAction::Rename(to) => {
+            if objects.contains_key(to) {
+                return Err(StepValidationError(format!(
+                    "{} {} already exists",
+                    kind, to
+                )));
+            }
+            let mut renamed = objects
+                .remove(&name)
+                .ok_or_else(|| StepValidationError(format!("{} {} does not exist", kind, name)))?;
+            set_name(&mut renamed, to.clone());
+            objects.insert(to.clone(), renamed);
+            Ok(())
+        }
+        // everything else has to happen on an existing object
+        _ => objects
+            .get(&name)
+            .map(|_| ())
+            .ok_or_else(|| StepValidationError(format!("{} {} does not exist", kind, name))),
+    }
+}
+
+/// objects that are identified by their name in the catalog
+trait HasName {
+    fn name(&self) -> Rc<str>;
+}
+
+macro_rules! has_name {
+    ($($t:ty),*) => {
+        $(impl HasName for $t {
+            fn name(&self) -> Rc<str> {
+                self.name.clone()
+            }
+        })*
+    };
+}
+
+has_name!(Database, Table, Index, Sequence, View, Namespace, Function, Trigger);
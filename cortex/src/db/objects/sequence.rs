@@ -1,24 +1,26 @@
+use std::rc::Rc;
+
+use crate::objects::statement::Statement;
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
-pub struct Sequence<'a, T> {
-    pub name: &'a str,
+pub struct Sequence {
+    pub name: Rc<str>,
     pub start: i64,
     pub increment: i64,
     pub min_value: i64,
     pub max_value: i64,
     pub cycle: bool,
-    _marker: std::marker::PhantomData<T>,
 }
 
-impl<'a, T> Sequence<'a, T> {
-    pub fn new(name: &'a str) -> Self {
+impl Sequence {
+    pub fn new(name: &str) -> Self {
         Self {
-            name,
+            name: Rc::from(name),
             start: 1,
             increment: 1,
             min_value: 1,
             max_value: 9223372036854775807,
             cycle: false,
-            _marker: std::marker::PhantomData,
         }
     }
 
@@ -47,3 +49,15 @@ impl<'a, T> Sequence<'a, T> {
         self
     }
 }
+
+impl From<Sequence> for Statement {
+    fn from(sequence: Sequence) -> Self {
+        Statement::Sequence(sequence)
+    }
+}
+
+impl From<&Sequence> for Statement {
+    fn from(sequence: &Sequence) -> Self {
+        Statement::Sequence(sequence.clone())
+    }
+}
@@ -1,12 +1,110 @@
-use crate::objects::{database::Database, table::Table};
+use std::rc::Rc;
+
+use serde::Serialize;
+
+use crate::objects::{
+    code::Code,
+    database::Database,
+    delete::{Filter, Truncate},
+    function::Function,
+    index::Index,
+    insert::Insert,
+    namespace::Namespace,
+    raw::Raw,
+    sequence::Sequence,
+    table::{PropType, Table, TableProp},
+    trigger::Trigger,
+    view::View,
+};
 
 #[derive(Clone)]
 /// A database action is an action that is run on the database.
+/// # Example
+/// ```
+/// use cortex::objects::step::{Step, StepType};
+/// use cortex::objects::statement::DbAction;
+/// use cortex::objects::table::Table;
+///
+/// let users = Table::new("users");
+/// let step = Step::new("rename users", StepType::Update, semver::Version::new(1, 0, 0))
+///    .add_statement(&users, DbAction::RenameColumn("name".into(), "full_name".into()))
+///    .add_statement(&users, DbAction::Rename("customers".into()));
+/// ```
 pub enum DbAction {
     Create,
     Drop,
-    Alter,
-    Insert,
+    /// Alters the structure of a table
+    Alter(Alter),
+    /// Inserts the given rows into a table
+    Insert(Insert),
+    /// Removes all rows of a table and keeps the table
+    Truncate(Truncate),
+    /// Removes the rows of a table matching the filter
+    Delete(Filter),
+    /// Renames the object of the statement to the given name
+    /// the statement keeps the old identity so the rename can be reversed
+    Rename(Rc<str>),
+    /// Renames a column of a table from the first to the second name
+    RenameColumn(Rc<str>, Rc<str>),
+    /// Executes the statement as it is, used for code and raw statements
+    Execute,
+}
+
+impl DbAction {
+    /// Short name of the action without its arguments
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DbAction::Create => "create",
+            DbAction::Drop => "drop",
+            DbAction::Alter(_) => "alter",
+            DbAction::Insert(_) => "insert",
+            DbAction::Truncate(_) => "truncate",
+            DbAction::Delete(_) => "delete",
+            DbAction::Rename(_) => "rename",
+            DbAction::RenameColumn(..) => "rename_column",
+            DbAction::Execute => "execute",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// How create and drop statements treat objects that already exist or are missing
+pub enum ExistencePolicy {
+    /// creating an existing object fails, missing objects are not dropped
+    #[default]
+    DropIfExists,
+    /// creating an existing or dropping a missing object fails
+    Strict,
+    /// existing objects are not created again and missing objects are not dropped
+    IfNotExists,
+    /// existing objects are replaced, objects which can not be replaced in place
+    /// are dropped and created again
+    CreateOrReplace,
+}
+
+#[derive(Clone)]
+/// Alterations of the structure of a table
+pub enum Alter {
+    /// Adds the given column
+    AddColumn(TableProp),
+    /// Drops the column with the given name
+    DropColumn(Rc<str>),
+    /// Changes the type of the column with the given name
+    /// the table of the statement holds the type before the change
+    ColumnType(Rc<str>, PropType),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// How much data a statement can lose when it is run
+pub enum ChangeSafety {
+    /// no data is lost
+    #[default]
+    Safe,
+    /// parts of the data can be lost, like a dropped column or a narrowed type
+    DataLosing,
+    /// whole objects and all of their data are lost
+    Destructive,
 }
 
 #[derive(Clone)]
@@ -14,4 +112,203 @@ pub enum DbAction {
 pub enum Statement {
     Table(Table),
     Database(Database),
+    Index(Index),
+    Sequence(Sequence),
+    View(View),
+    Namespace(Namespace),
+    Function(Function),
+    Trigger(Trigger),
+    Code(Code),
+    Raw(Raw),
+}
+
+impl Statement {
+    /// The name of the object the statement is run on
+    pub fn name(&self) -> Rc<str> {
+        match self {
+            Statement::Table(t) => t.name.clone(),
+            Statement::Database(d) => d.name.clone(),
+            Statement::Index(i) => i.name.clone(),
+            Statement::Sequence(s) => s.name.clone(),
+            Statement::View(v) => v.name.clone(),
+            Statement::Namespace(n) => n.name.clone(),
+            Statement::Function(f) => f.name.clone(),
+            Statement::Trigger(t) => t.name.clone(),
+            Statement::Code(c) => c.name.clone(),
+            Statement::Raw(r) => r.name.clone(),
+        }
+    }
+
+    /// Names of the objects the object of the statement depends on
+    /// # Example
+    /// ```
+    /// use cortex::objects::index::Index;
+    /// use cortex::objects::statement::Statement;
+    /// let index = Statement::from(Index::new("users_id_idx", "users"));
+    /// assert_eq!(index.requires(), vec!["users".into()]);
+    /// ```
+    pub fn requires(&self) -> Vec<Rc<str>> {
+        match self {
+            Statement::Table(t) => t.references(),
+            Statement::Index(i) => vec![i.table.clone()],
+            Statement::View(v) => v.depends_on.clone(),
+            Statement::Trigger(t) => vec![t.table.clone(), t.function.clone()],
+            _ => Vec::new(),
+        }
+    }
+
+    /// A copy of the statement with the object renamed to the given name
+    pub fn renamed(&self, name: &Rc<str>) -> Statement {
+        let mut statement = self.clone();
+        match &mut statement {
+            Statement::Table(t) => t.name = name.clone(),
+            Statement::Database(d) => d.name = name.clone(),
+            Statement::Index(i) => i.name = name.clone(),
+            Statement::Sequence(s) => s.name = name.clone(),
+            Statement::View(v) => v.name = name.clone(),
+            Statement::Namespace(n) => n.name = name.clone(),
+            Statement::Function(f) => f.name = name.clone(),
+            Statement::Trigger(t) => t.name = name.clone(),
+            Statement::Code(c) => c.name = name.clone(),
+            Statement::Raw(r) => r.name = name.clone(),
+        }
+        statement
+    }
+
+    /// Derives the statement which reverts this one
+    /// returns None if the action can not be reverted without knowing the lost data
+    /// # Example
+    /// ```
+    /// use cortex::objects::statement::{DbAction, Statement};
+    /// use cortex::objects::table::Table;
+    /// let users = Statement::from(Table::new("users"));
+    /// let (reverse, action) = users.reverse(&DbAction::Rename("customers".into())).unwrap();
+    /// assert_eq!(reverse.name(), "customers".into());
+    /// assert!(matches!(action, DbAction::Rename(name) if name == "users".into()));
+    /// assert!(users.reverse(&DbAction::Drop).is_none());
+    /// ```
+    pub fn reverse(&self, action: &DbAction) -> Option<(Statement, DbAction)> {
+        match (self, action) {
+            (Statement::Code(_) | Statement::Raw(_), _) => None,
+            (statement, DbAction::Create) => Some((statement.clone(), DbAction::Drop)),
+            (statement, DbAction::Rename(name)) => {
+                Some((statement.renamed(name), DbAction::Rename(statement.name())))
+            }
+            (Statement::Table(table), DbAction::RenameColumn(from, to)) => {
+                let mut table = table.clone();
+                table
+                    .props
+                    .iter_mut()
+                    .filter(|p| p.name == *from)
+                    .for_each(|p| p.name = to.clone());
+                Some((
+                    Statement::Table(table),
+                    DbAction::RenameColumn(to.clone(), from.clone()),
+                ))
+            }
+            (statement, DbAction::RenameColumn(from, to)) => Some((
+                statement.clone(),
+                DbAction::RenameColumn(to.clone(), from.clone()),
+            )),
+            (statement, DbAction::Alter(Alter::AddColumn(prop))) => Some((
+                statement.clone(),
+                DbAction::Alter(Alter::DropColumn(prop.name.clone())),
+            )),
+            (Statement::Table(table), DbAction::Alter(Alter::ColumnType(column, to))) => {
+                let mut table = table.clone();
+                let prop = table.props.iter_mut().find(|p| p.name == *column)?;
+                let from = std::mem::replace(&mut prop.t_type, to.clone());
+                Some((
+                    Statement::Table(table),
+                    DbAction::Alter(Alter::ColumnType(column.clone(), from)),
+                ))
+            }
+            (_, DbAction::Drop)
+            | (_, DbAction::Alter(Alter::DropColumn(_)))
+            | (_, DbAction::Alter(Alter::ColumnType(_, _)))
+            | (_, DbAction::Insert(_))
+            | (_, DbAction::Truncate(_))
+            | (_, DbAction::Delete(_))
+            | (_, DbAction::Execute) => None,
+        }
+    }
+
+    /// Classifies how much data the statement can lose
+    /// code is opaque and counts as destructive, raw statements are checked for the known statements
+    /// # Example
+    /// ```
+    /// use cortex::objects::raw::Raw;
+    /// use cortex::objects::statement::{Alter, ChangeSafety, DbAction, Statement};
+    /// use cortex::objects::table::{PropType, Table};
+    /// let users = Statement::from(Table::new("users").add_prop(("id", PropType::BigInt, None)));
+    /// assert_eq!(users.safety(&DbAction::Create), ChangeSafety::Safe);
+    /// assert_eq!(users.safety(&DbAction::Drop), ChangeSafety::Destructive);
+    /// let narrow = DbAction::Alter(Alter::ColumnType("id".into(), PropType::Int));
+    /// assert_eq!(users.safety(&narrow), ChangeSafety::DataLosing);
+    /// let raw = Statement::from(Raw::new("cleanup").on_postgres("DROP TABLE users; TRUNCATE orders;"));
+    /// assert_eq!(raw.safety(&DbAction::Execute), ChangeSafety::Destructive);
+    /// let raw = Statement::from(Raw::new("enable_trgm").on_postgres("CREATE EXTENSION pg_trgm;"));
+    /// assert_eq!(raw.safety(&DbAction::Execute), ChangeSafety::Safe);
+    /// ```
+    pub fn safety(&self, action: &DbAction) -> ChangeSafety {
+        match (self, action) {
+            (Statement::Code(_), _) => ChangeSafety::Destructive,
+            (Statement::Raw(raw), _) => raw_safety(raw),
+            (
+                Statement::Table(_) | Statement::Database(_) | Statement::Namespace(_),
+                DbAction::Drop,
+            ) => ChangeSafety::Destructive,
+            (Statement::Sequence(_), DbAction::Drop) => ChangeSafety::DataLosing,
+            (_, DbAction::Truncate(_)) => ChangeSafety::Destructive,
+            (_, DbAction::Delete(_)) => ChangeSafety::DataLosing,
+            (_, DbAction::Alter(Alter::DropColumn(_))) => ChangeSafety::DataLosing,
+            (Statement::Table(table), DbAction::Alter(Alter::ColumnType(column, to))) => {
+                match table.props.iter().find(|p| p.name == *column) {
+                    Some(prop) if prop.t_type.widens_to(to) => ChangeSafety::Safe,
+                    _ => ChangeSafety::DataLosing,
+                }
+            }
+            _ => ChangeSafety::Safe,
+        }
+    }
+}
+
+/// the safety of raw statements, the sql and the mongodb command are checked
+/// for the known statements that lose data
+fn raw_safety(raw: &Raw) -> ChangeSafety {
+    let sql = raw
+        .postgres
+        .iter()
+        .chain(raw.sqlite.iter())
+        .map(|sql| sql.to_uppercase())
+        .collect::<Vec<String>>()
+        .join("\n");
+    let sql_safety = if ["DROP TABLE", "DROP DATABASE", "DROP SCHEMA", "TRUNCATE"]
+        .iter()
+        .any(|keyword| sql.contains(keyword))
+    {
+        ChangeSafety::Destructive
+    } else if ["DELETE ", "UPDATE ", "DROP COLUMN", "DROP SEQUENCE"]
+        .iter()
+        .any(|keyword| sql.contains(keyword))
+        || (sql.contains("ALTER COLUMN") && sql.contains(" TYPE "))
+    {
+        ChangeSafety::DataLosing
+    } else {
+        ChangeSafety::Safe
+    };
+    #[cfg(feature = "mongodb")]
+    let command_safety = match raw
+        .mongodb
+        .as_ref()
+        .and_then(|(_, command)| command.keys().next())
+        .map(String::as_str)
+    {
+        Some("drop" | "dropDatabase") => ChangeSafety::Destructive,
+        Some("delete" | "update" | "findAndModify") => ChangeSafety::DataLosing,
+        _ => ChangeSafety::Safe,
+    };
+    #[cfg(not(feature = "mongodb"))]
+    let command_safety = ChangeSafety::Safe;
+    sql_safety.max(command_safety)
 }
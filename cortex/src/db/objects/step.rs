@@ -1,6 +1,14 @@
-use std::rc::Rc;
+use std::{rc::Rc, time::Duration};
 
-use crate::{db::objects::statement::Statement, objects::statement::DbAction};
+use serde::Serialize;
+
+use crate::{
+    db::{
+        cortex::{CortexError, DestructiveChangeError, StepValidationError},
+        objects::statement::Statement,
+    },
+    objects::statement::{ChangeSafety, DbAction, ExistencePolicy},
+};
 
 #[derive(Clone)]
 /// Steps are the main unit of work in the migration system.
@@ -20,9 +28,37 @@ pub struct Step {
     pub statements: Vec<(Statement, DbAction)>,
     /// The version of the step
     pub version: semver::Version,
+    /// Hand written statements that revert the step
+    /// if empty they are derived from the statements
+    pub reverse: Vec<(Statement, DbAction)>,
+    /// The most data losing change the step is allowed to run
+    pub allowed_safety: ChangeSafety,
+    /// How creates and drops of the step treat existing and missing objects
+    pub policy: ExistencePolicy,
+    /// Run the statements ordered by the references between their objects
+    pub dependency_order: bool,
+    /// Whether the statements run inside a transaction
+    pub transaction: StepTransaction,
+    /// How long a statement of the step may run, overrides the config of cortex
+    pub statement_timeout: Option<Duration>,
+    /// How long a statement of the step may wait for a lock, overrides the config of cortex
+    pub lock_timeout: Option<Duration>,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+/// Whether the statements of a step run inside a transaction
+pub enum StepTransaction {
+    /// follow the execution mode of cortex
+    #[default]
+    Inherit,
+    /// run without a transaction, for statements which cannot run in one
+    Without,
+    /// run in a transaction of its own even if cortex runs optimistic
+    Own,
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum StepType {
     /// The step is a setup step, which is run before the main update step.
     InitSetup,
@@ -39,9 +75,140 @@ impl Step {
             s_type,
             statements: Vec::new(),
             version,
+            reverse: Vec::new(),
+            allowed_safety: ChangeSafety::Safe,
+            policy: ExistencePolicy::DropIfExists,
+            dependency_order: false,
+            transaction: StepTransaction::Inherit,
+            statement_timeout: None,
+            lock_timeout: None,
         }
     }
 
+    /// Run the statements without a transaction
+    /// for statements like `CREATE DATABASE` or concurrent indexes which cannot run in one
+    /// # Example
+    /// ```
+    /// use cortex::objects::index::Index;
+    /// use cortex::objects::statement::DbAction;
+    /// use cortex::objects::step::{Step, StepTransaction, StepType};
+    ///
+    /// let index = Index::new("users_id_idx", "users").add_column("id").concurrently();
+    /// let step = Step::new("index", StepType::Update, semver::Version::new(1, 0, 0))
+    ///    .add_statement(&index, DbAction::Create)
+    ///    .without_transaction();
+    /// assert_eq!(step.transaction, StepTransaction::Without);
+    /// ```
+    pub fn without_transaction(mut self) -> Self {
+        self.transaction = StepTransaction::Without;
+        self
+    }
+
+    /// Run the statements in a transaction of their own
+    /// even if cortex runs in optimistic mode
+    pub fn own_transaction(mut self) -> Self {
+        self.transaction = StepTransaction::Own;
+        self
+    }
+
+    /// Limit how long each statement of the step may run
+    /// for steps which are expected to be slower or faster than the rest
+    /// # Example
+    /// ```
+    /// use std::time::Duration;
+    /// use cortex::objects::step::{Step, StepType};
+    ///
+    /// let step = Step::new("backfill", StepType::Update, semver::Version::new(1, 0, 0))
+    ///    .statement_timeout(Duration::from_secs(600))
+    ///    .lock_timeout(Duration::from_secs(2));
+    /// assert_eq!(step.statement_timeout, Some(Duration::from_secs(600)));
+    /// ```
+    pub fn statement_timeout(mut self, timeout: Duration) -> Self {
+        self.statement_timeout = Some(timeout);
+        self
+    }
+
+    /// Limit how long each statement of the step may wait for a lock
+    /// only supported by postgres
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.lock_timeout = Some(timeout);
+        self
+    }
+
+    /// Run the statements ordered by the references between their objects
+    /// instead of the order they were added in
+    /// # Example
+    /// ```
+    /// use cortex::objects::step::{Step, StepType};
+    /// use cortex::objects::statement::DbAction;
+    /// use cortex::objects::table::{PropAnnotation, PropType, Table};
+    ///
+    /// let users = Table::new("users").add_prop(("id", PropType::Int, None));
+    /// let orders = Table::new("orders").add_prop((
+    ///     "user_id",
+    ///     PropType::Int,
+    ///     Some(PropAnnotation::References("users".into(), "id".into())),
+    /// ));
+    /// let step = Step::new("shop", StepType::Update, semver::Version::new(1, 0, 0))
+    ///    .add_statement(&orders, DbAction::Create)
+    ///    .add_statement(&users, DbAction::Create)
+    ///    .order_by_dependencies();
+    /// let ordered = step.ordered_statements().unwrap();
+    /// assert_eq!(ordered[0].0.name(), "users".into());
+    /// assert_eq!(ordered[1].0.name(), "orders".into());
+    /// ```
+    pub fn order_by_dependencies(mut self) -> Self {
+        self.dependency_order = true;
+        self
+    }
+
+    /// Set how creates and drops treat existing and missing objects
+    /// steps skip drops of missing objects with [`ExistencePolicy::DropIfExists`] by default
+    /// a step with [`ExistencePolicy::IfNotExists`] can be run again
+    /// after it was only applied partially
+    /// # Example
+    /// ```
+    /// use cortex::objects::step::{Step, StepType};
+    /// use cortex::objects::statement::{DbAction, ExistencePolicy};
+    /// use cortex::objects::table::Table;
+    ///
+    /// let step = Step::new("users", StepType::Update, semver::Version::new(1, 0, 0))
+    ///    .add_statement(Table::new("users"), DbAction::Create);
+    /// assert_eq!(step.policy, ExistencePolicy::DropIfExists);
+    /// let step = step.with_policy(ExistencePolicy::IfNotExists);
+    /// assert_eq!(step.policy, ExistencePolicy::IfNotExists);
+    /// ```
+    pub fn with_policy(mut self, policy: ExistencePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Allow the step to run statements which lose parts of the data
+    /// like dropping columns or narrowing column types
+    pub fn allow_data_loss(mut self) -> Self {
+        self.allowed_safety = self.allowed_safety.max(ChangeSafety::DataLosing);
+        self
+    }
+
+    /// Allow the step to run statements which lose whole objects
+    /// like dropping tables or databases, implies [`Step::allow_data_loss`]
+    /// # Example
+    /// ```
+    /// use cortex::objects::database::Database;
+    /// use cortex::objects::step::{Step, StepType};
+    /// use cortex::objects::statement::DbAction;
+    ///
+    /// let db = Database::new("test");
+    /// let step = Step::new("cleanup", StepType::Update, semver::Version::new(1, 0, 0))
+    ///    .add_statement(&db, DbAction::Drop);
+    /// assert!(step.check_safety().is_err());
+    /// assert!(step.allow_destructive().check_safety().is_ok());
+    /// ```
+    pub fn allow_destructive(mut self) -> Self {
+        self.allowed_safety = ChangeSafety::Destructive;
+        self
+    }
+
     /// Add a statement to the step.
     /// # Example
     /// ```
@@ -77,4 +244,246 @@ impl Step {
             .extend(statements.into_iter().map(|(s, a)| (s.into(), a)));
         self
     }
+
+    /// Add a hand written statement that reverts the step
+    /// once added the reverse statements are no longer derived
+    /// # Example
+    /// ```
+    /// use cortex::objects::step::{Step, StepType};
+    /// use cortex::objects::statement::DbAction;
+    /// use cortex::objects::table::Table;
+    ///
+    /// let archive = Table::new("archive");
+    /// let step = Step::new("drop archive", StepType::Update, semver::Version::new(1, 0, 0))
+    ///    .add_statement(&archive, DbAction::Drop)
+    ///    .add_reverse_statement(&archive, DbAction::Create);
+    /// assert_eq!(step.reverse_statements().unwrap().len(), 1);
+    /// ```
+    pub fn add_reverse_statement(
+        mut self,
+        statement: impl Into<Statement>,
+        action: DbAction,
+    ) -> Self {
+        self.reverse.push((statement.into(), action));
+        self
+    }
+
+    /// The statements that revert the step in the order they have to run
+    /// hand written ones if present, otherwise derived from the statements
+    /// # Example
+    /// ```
+    /// use cortex::objects::step::{Step, StepType};
+    /// use cortex::objects::statement::DbAction;
+    /// use cortex::objects::table::Table;
+    ///
+    /// let users = Table::new("users");
+    /// let step = Step::new("users", StepType::Update, semver::Version::new(1, 0, 0))
+    ///    .add_statement(&users, DbAction::Create)
+    ///    .add_statement(&users, DbAction::Rename("customers".into()));
+    /// let reverse = step.reverse_statements().unwrap();
+    /// assert_eq!(reverse[0].0.name(), "customers".into());
+    /// assert!(matches!(reverse[1].1, DbAction::Drop));
+    /// ```
+    pub fn reverse_statements(&self) -> Result<Vec<(Statement, DbAction)>, StepValidationError> {
+        if !self.reverse.is_empty() {
+            return Ok(self.reverse.clone());
+        }
+        self.ordered_statements()?
+            .iter()
+            .rev()
+            .map(|(statement, action)| {
+                statement.reverse(action).ok_or_else(|| {
+                    StepValidationError(format!(
+                        "{} of step {} {} can not be reverted, add reverse statements",
+                        statement.name(),
+                        self.name,
+                        self.version
+                    ))
+                })
+            })
+            .collect()
+    }
+
+    /// Validates the statements of the step before anything is sent to the database
+    /// # Example
+    /// ```
+    /// use cortex::objects::insert::{Insert, Row};
+    /// use cortex::objects::step::{Step, StepType};
+    /// use cortex::objects::statement::DbAction;
+    /// use cortex::objects::table::{PropType, Table};
+    ///
+    /// let countries = Table::new("countries").add_prop(("code", PropType::Text, None));
+    /// let step = Step::new("countries", StepType::Update, semver::Version::new(1, 0, 0))
+    ///    .add_statement(&countries, DbAction::Create)
+    ///    .add_statement(
+    ///        &countries,
+    ///        DbAction::Insert(Insert::new().add_row(Row::new().set("code", 49))),
+    ///    );
+    /// assert!(step.validate().is_err());
+    /// ```
+    pub fn validate(&self) -> Result<(), StepValidationError> {
+        for (statement, action) in &self.ordered_statements()? {
+            match (statement, action) {
+                (Statement::Table(table), DbAction::Insert(insert)) => insert.validate(table)?,
+                (Statement::Table(table), DbAction::Delete(filter)) => filter.validate(table)?,
+                (Statement::Table(_), _) => {}
+                (statement, DbAction::Insert(_) | DbAction::Truncate(_) | DbAction::Delete(_)) => {
+                    return Err(StepValidationError(format!(
+                        "{} is not a table rows can be inserted into or deleted from",
+                        statement.name()
+                    )))
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the step does not lose more data than it allows
+    /// replacing an object on create loses as much as dropping it
+    pub fn check_safety(&self) -> Result<(), DestructiveChangeError> {
+        self.check_statements_safety(&self.statements, "run it")
+    }
+
+    /// Checks that reverting the step does not lose more data than it allows
+    /// the reverse of a create drops the object with all of its data
+    /// # Example
+    /// ```
+    /// use cortex::objects::step::{Step, StepType};
+    /// use cortex::objects::statement::DbAction;
+    /// use cortex::objects::table::Table;
+    ///
+    /// let users = Table::new("users");
+    /// let step = Step::new("users", StepType::Update, semver::Version::new(1, 0, 0))
+    ///    .add_statement(&users, DbAction::Create);
+    /// assert!(step.check_safety().is_ok());
+    /// assert!(step.check_reverse_safety().is_err());
+    /// assert!(step.allow_destructive().check_reverse_safety().is_ok());
+    /// ```
+    pub fn check_reverse_safety(&self) -> Result<(), CortexError> {
+        self.check_statements_safety(&self.reverse_statements()?, "revert it")?;
+        Ok(())
+    }
+
+    fn check_statements_safety(
+        &self,
+        statements: &[(Statement, DbAction)],
+        purpose: &str,
+    ) -> Result<(), DestructiveChangeError> {
+        for (statement, action) in statements {
+            let safety = self.statement_safety(statement, action);
+            if safety > self.allowed_safety {
+                let allow = match safety {
+                    ChangeSafety::Destructive => "allow_destructive",
+                    _ => "allow_data_loss",
+                };
+                return Err(DestructiveChangeError(format!(
+                    "{} in step {} {} is {:?}, mark the step with {} to {}",
+                    statement.name(),
+                    self.name,
+                    self.version,
+                    safety,
+                    allow,
+                    purpose
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// How much data the statement loses when it is run with the policy of the step
+    pub fn statement_safety(&self, statement: &Statement, action: &DbAction) -> ChangeSafety {
+        match (&self.policy, action) {
+            (ExistencePolicy::CreateOrReplace, DbAction::Create) => {
+                statement.safety(&DbAction::Drop)
+            }
+            _ => statement.safety(action),
+        }
+    }
+
+    /// The statements in the order they are run
+    /// sorted by the references between their objects if the step asks for it,
+    /// creates run after the objects they reference and drops before them
+    /// # Example
+    /// ```
+    /// use cortex::objects::step::{Step, StepType};
+    /// use cortex::objects::statement::DbAction;
+    /// use cortex::objects::view::View;
+    ///
+    /// let a = View::new("a", "SELECT * FROM b").depends_on("b");
+    /// let b = View::new("b", "SELECT * FROM a").depends_on("a");
+    /// let step = Step::new("views", StepType::Update, semver::Version::new(1, 0, 0))
+    ///    .add_statement(&a, DbAction::Create)
+    ///    .add_statement(&b, DbAction::Create)
+    ///    .order_by_dependencies();
+    /// assert!(step.ordered_statements().is_err());
+    /// ```
+    pub fn ordered_statements(&self) -> Result<Vec<(Statement, DbAction)>, StepValidationError> {
+        if !self.dependency_order {
+            return Ok(self.statements.clone());
+        }
+        let len = self.statements.len();
+        // objects a statement works on, renames work on the old and the new name
+        let objects = self
+            .statements
+            .iter()
+            .map(|(statement, action)| match (statement, action) {
+                (Statement::Code(_) | Statement::Raw(_), _) => Vec::new(),
+                (statement, DbAction::Rename(name)) => vec![statement.name(), name.clone()],
+                (statement, _) => vec![statement.name()],
+            })
+            .collect::<Vec<Vec<Rc<str>>>>();
+        let opaque = |s: &Statement| matches!(s, Statement::Code(_) | Statement::Raw(_));
+        let mut edges = vec![Vec::new(); len];
+        for i in 0..len {
+            let (statement, action) = &self.statements[i];
+            let requires = statement.requires();
+            for j in 0..len {
+                if i == j {
+                    continue;
+                }
+                let (other, other_action) = &self.statements[j];
+                // code and raw statements keep their position
+                let keep_order = (opaque(statement) || opaque(other))
+                    // statements on the same object keep their order
+                    || objects[i].iter().any(|o| objects[j].contains(o));
+                if keep_order && i < j {
+                    edges[i].push(j);
+                }
+                if !requires.iter().any(|r| objects[j].contains(r)) {
+                    continue;
+                }
+                match (action, other_action) {
+                    // dependents are dropped before the objects they reference
+                    (DbAction::Drop, DbAction::Drop) => edges[i].push(j),
+                    (DbAction::Drop, _) | (_, DbAction::Drop) => {}
+                    // and created after them
+                    _ => edges[j].push(i),
+                }
+            }
+        }
+        let mut incoming = vec![0; len];
+        edges.iter().flatten().for_each(|&j| incoming[j] += 1);
+        let mut done = vec![false; len];
+        let mut order = Vec::with_capacity(len);
+        // always pick the earliest ready statement to keep the insertion order where possible
+        while let Some(next) = (0..len).find(|&i| !done[i] && incoming[i] == 0) {
+            done[next] = true;
+            order.push(self.statements[next].clone());
+            edges[next].iter().for_each(|&j| incoming[j] -= 1);
+        }
+        if order.len() < len {
+            let cycle = (0..len)
+                .filter(|&i| !done[i])
+                .map(|i| self.statements[i].0.name().to_string())
+                .collect::<Vec<String>>();
+            return Err(StepValidationError(format!(
+                "step {} {} has a dependency cycle between {}",
+                self.name,
+                self.version,
+                cycle.join(", ")
+            )));
+        }
+        Ok(order)
+    }
 }
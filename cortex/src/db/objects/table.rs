@@ -24,7 +24,7 @@ pub struct TableProp {
     pub annotation: Option<PropAnnotation>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 /// PropType enum for defining the type of a property
 pub enum PropType {
     Int,
@@ -38,6 +38,36 @@ pub enum PropType {
     // ...
 }
 
+impl PropType {
+    /// checks if every value of this type can be converted to the given type
+    /// without losing information
+    /// # Example
+    /// ```
+    /// use cortex::objects::table::PropType;
+    /// assert!(PropType::Int.widens_to(&PropType::BigInt));
+    /// assert!(!PropType::BigInt.widens_to(&PropType::Int));
+    /// ```
+    pub fn widens_to(&self, to: &PropType) -> bool {
+        matches!(
+            (self, to),
+            (_, PropType::Text)
+                | (
+                    PropType::SmallInt,
+                    PropType::SmallInt | PropType::Int | PropType::BigInt | PropType::Double
+                )
+                | (
+                    PropType::Int,
+                    PropType::Int | PropType::BigInt | PropType::Double
+                )
+                | (PropType::BigInt, PropType::BigInt)
+                | (PropType::Double, PropType::Double)
+                | (PropType::Bool, PropType::Bool)
+                | (PropType::Date, PropType::Date | PropType::Timestamp)
+                | (PropType::Timestamp, PropType::Timestamp)
+        )
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 /// PropAnnotation enum for defining the annotation of a property
 pub enum PropAnnotation {
@@ -47,6 +77,8 @@ pub enum PropAnnotation {
     Default,
     Check,
     Foreign,
+    /// Foreign key referencing the column of the given table
+    References(Rc<str>, Rc<str>),
     Constraint(Box<PropAnnotation>),
     #[default]
     Empty,
@@ -156,6 +188,27 @@ impl Table {
         self.annotations.push(annotation);
         self
     }
+
+    /// Tables the props of the table reference with foreign keys
+    /// # Example
+    /// ```
+    /// use cortex::objects::table::{PropAnnotation, PropType, Table};
+    /// let orders = Table::new("orders").add_prop((
+    ///     "user_id",
+    ///     PropType::Int,
+    ///     Some(PropAnnotation::References("users".into(), "id".into())),
+    /// ));
+    /// assert_eq!(orders.references(), vec!["users".into()]);
+    /// ```
+    pub fn references(&self) -> Vec<Rc<str>> {
+        self.props
+            .iter()
+            .filter_map(|p| match &p.annotation {
+                Some(PropAnnotation::References(table, _)) => Some(table.clone()),
+                _ => None,
+            })
+            .collect()
+    }
 }
 
 impl From<Table> for Statement {
@@ -170,6 +223,12 @@ impl From<&Table> for Statement {
     }
 }
 
+impl From<&Table> for Rc<str> {
+    fn from(table: &Table) -> Self {
+        table.name.clone()
+    }
+}
+
 impl TableProp {
     /// Create a new property
     /// # Example
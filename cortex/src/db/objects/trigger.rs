@@ -0,0 +1,84 @@
+use std::rc::Rc;
+
+use crate::objects::statement::Statement;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+/// TriggerTiming enum for defining when a trigger fires
+pub enum TriggerTiming {
+    Before,
+    #[default]
+    After,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// TriggerEvent enum for defining which changes fire a trigger
+pub enum TriggerEvent {
+    Insert,
+    Update,
+    Delete,
+}
+
+#[derive(Debug, Clone)]
+/// Trigger struct for running a function on changes of a table
+pub struct Trigger {
+    /// name of the trigger
+    pub name: Rc<str>,
+    /// table the trigger is created on
+    pub table: Rc<str>,
+    /// function the trigger runs
+    pub function: Rc<str>,
+    /// whether the trigger fires before or after the change
+    pub timing: TriggerTiming,
+    /// changes the trigger fires on
+    pub events: Vec<TriggerEvent>,
+}
+
+impl Trigger {
+    /// Create a new trigger running the function after changes of the table
+    /// # Example
+    /// ```
+    /// use cortex::objects::function::Function;
+    /// use cortex::objects::table::Table;
+    /// use cortex::objects::trigger::{Trigger, TriggerEvent, TriggerTiming};
+    /// let users = Table::new("users");
+    /// let touch = Function::new("touch", "BEGIN NEW.updated_at = now(); RETURN NEW; END;");
+    /// let trigger = Trigger::new("users_touch", &users, &touch)
+    ///    .before()
+    ///    .on(TriggerEvent::Update);
+    /// assert_eq!(trigger.function, "touch".into());
+    /// assert_eq!(trigger.timing, TriggerTiming::Before);
+    /// ```
+    pub fn new(name: &str, table: impl Into<Rc<str>>, function: impl Into<Rc<str>>) -> Self {
+        Self {
+            name: Rc::from(name),
+            table: table.into(),
+            function: function.into(),
+            timing: TriggerTiming::default(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Fire the trigger before the change
+    pub fn before(mut self) -> Self {
+        self.timing = TriggerTiming::Before;
+        self
+    }
+
+    /// Add a change the trigger fires on
+    pub fn on(mut self, event: TriggerEvent) -> Self {
+        self.events.push(event);
+        self
+    }
+}
+
+impl From<Trigger> for Statement {
+    fn from(trigger: Trigger) -> Self {
+        Statement::Trigger(trigger)
+    }
+}
+
+impl From<&Trigger> for Statement {
+    fn from(trigger: &Trigger) -> Self {
+        Statement::Trigger(trigger.clone())
+    }
+}
@@ -0,0 +1,65 @@
+use std::rc::Rc;
+
+use crate::objects::statement::Statement;
+
+#[derive(Debug, Clone)]
+/// View struct for creating views from a query
+pub struct View {
+    /// name of the view
+    pub name: Rc<str>,
+    /// query the view is defined by
+    pub query: Rc<str>,
+    /// tables and views the query selects from
+    pub depends_on: Vec<Rc<str>>,
+}
+
+impl View {
+    /// Create a new view from the given query
+    /// # Example
+    /// ```
+    /// use cortex::objects::view::View;
+    /// let view = View::new("active_users", "SELECT * FROM users WHERE active");
+    /// assert_eq!(view.name, "active_users".into());
+    /// ```
+    pub fn new(name: &str, query: &str) -> Self {
+        Self {
+            name: Rc::from(name),
+            query: Rc::from(query),
+            depends_on: Vec::new(),
+        }
+    }
+
+    /// Add a table or view the query selects from
+    /// used to order the statements of a step by their dependencies
+    /// # Example
+    /// ```
+    /// use cortex::objects::table::Table;
+    /// use cortex::objects::view::View;
+    /// let users = Table::new("users");
+    /// let view = View::new("active_users", "SELECT * FROM users WHERE active")
+    ///    .depends_on(&users);
+    /// assert_eq!(view.depends_on[0], "users".into());
+    /// ```
+    pub fn depends_on(mut self, object: impl Into<Rc<str>>) -> Self {
+        self.depends_on.push(object.into());
+        self
+    }
+}
+
+impl From<View> for Statement {
+    fn from(view: View) -> Self {
+        Statement::View(view)
+    }
+}
+
+impl From<&View> for Statement {
+    fn from(view: &View) -> Self {
+        Statement::View(view.clone())
+    }
+}
+
+impl From<&View> for Rc<str> {
+    fn from(view: &View) -> Self {
+        view.name.clone()
+    }
+}
@@ -1,116 +1,476 @@
-use mongodb::{bson::doc, ClientSession};
-use mongodb::{options::CreateCollectionOptions, Client};
+use std::rc::Rc;
+
+use mongodb::bson::{doc, Bson, DateTime, Document, Timestamp};
 
 use crate::{
     connection::ExecuteError,
+    db::cortex::StepValidationError,
     objects::{
-        database::Database,
-        statement::DbAction,
+        delete::Filter,
+        insert::{Insert, OnConflict, Value},
+        statement::{Alter, DbAction, ExistencePolicy, Statement},
         table::{PropAnnotation, PropType, Table, TableProp},
     },
 };
 
 pub(crate) struct MongodbStatementProducer;
 
+/// A command document and the database it is run against
+pub(crate) struct MongoCommand {
+    pub database: Rc<str>,
+    pub command: Document,
+    /// the command is skipped if the precondition does not hold
+    pub precondition: Option<Precondition>,
+}
+
+impl MongoCommand {
+    /// the command for logs and errors, only its name and collection
+    /// documents like inserted values are left out
+    pub(crate) fn redacted(&self) -> String {
+        match self.command.iter().next() {
+            Some((name, collection)) => format!("{}.{} {}", self.database, name, collection),
+            None => self.database.to_string(),
+        }
+    }
+}
+
+/// Checked with `list_collection_names` before a command is run
+pub(crate) enum Precondition {
+    CollectionExists(Rc<str>),
+    CollectionMissing(Rc<str>),
+}
+
 impl MongodbStatementProducer {
-    #[allow(dead_code)]
-    pub(crate) fn database_statement(
-        (_client, ref mut _session): (&Client, &mut ClientSession),
-        _database: &Database,
-        _action: &DbAction,
-    ) -> Result<(), ExecuteError> {
-        unimplemented!("mongodb does not require explicit database creation")
+    /// checks if the statement can be run on mongodb with the action
+    /// everything map can not render is rejected before the first step runs
+    pub(crate) fn validate(
+        statement: &Statement,
+        action: &DbAction,
+    ) -> Result<(), StepValidationError> {
+        match statement {
+            Statement::Code(code) if code.mongodb.is_none() => Err(StepValidationError(format!(
+                "code {} has no mongodb variant",
+                code.name
+            ))),
+            Statement::Raw(raw) if raw.mongodb.is_none() => Err(StepValidationError(format!(
+                "raw statement {} has no mongodb variant",
+                raw.name
+            ))),
+            Statement::Code(_) | Statement::Raw(_) | Statement::Database(_) => Ok(()),
+            Statement::Table(table) if table.database.is_none() => Err(StepValidationError(
+                format!("collection {} has no database", table.name),
+            )),
+            // rendering converts the values of rows and filters, which can fail
+            Statement::Table(table) => MongodbStatementProducer::collection_statement(
+                table,
+                action,
+                &ExistencePolicy::Strict,
+            )
+            .map(|_| ())
+            .map_err(|e| {
+                StepValidationError(format!("{} of {}: {}", action.kind(), table.name, e.1))
+            }),
+            statement => Err(StepValidationError(format!(
+                "{} of {} is not supported by mongodb",
+                action.kind(),
+                statement.name()
+            ))),
+        }
     }
 
-    pub(crate) async fn collection_statement(
-        (client, session): (&Client, Option<&mut ClientSession>),
+    /// Why the statement cannot run inside a transaction, if it cannot
+    /// checks the commands it renders to, raw commands included
+    pub(crate) fn transaction_blocker(
+        statement: &Statement,
+        action: &DbAction,
+        policy: &ExistencePolicy,
+    ) -> Option<String> {
+        let commands = MongodbStatementProducer::map(statement, action, policy).ok()?;
+        commands.iter().find_map(|command| {
+            let (name, _) = command.command.iter().next()?;
+            match name.as_str() {
+                "drop" | "dropDatabase" | "renameCollection" | "collMod" => {
+                    Some(format!("{} cannot run inside a transaction", name))
+                }
+                _ => None,
+            }
+        })
+    }
+
+    /// maps a statement to the command documents that have to be run for it
+    pub(crate) fn map(
+        statement: &Statement,
+        action: &DbAction,
+        policy: &ExistencePolicy,
+    ) -> Result<Vec<MongoCommand>, ExecuteError> {
+        match statement {
+            Statement::Table(t) => {
+                MongodbStatementProducer::collection_statement(t, action, policy)
+            }
+            // mongodb does not require explicit database creation
+            Statement::Database(_) => Ok(Vec::new()),
+            Statement::Raw(raw) => {
+                let (database, command) = raw
+                    .mongodb
+                    .clone()
+                    .expect("raw statements are validated before execution");
+                Ok(vec![MongoCommand {
+                    database,
+                    command,
+                    precondition: None,
+                }])
+            }
+            statement => Err(ExecuteError(
+                statement.name().to_string(),
+                "statement is not supported by mongodb".to_string(),
+            )),
+        }
+    }
+
+    fn collection_statement(
         collection: &Table,
-        _action: &DbAction,
-    ) -> Result<(), ExecuteError> {
-        let db = client.database(collection.database.as_ref().expect("database not set"));
-        let schema = doc! {
-            "$jsonSchema": doc! {
-                "bsonType": "object",
-                "required": collection.props.iter().map(|p| p.name.to_string()).collect::<Vec<String>>(),
-                // create multiple documents from props iter
-                "properties": collection.props.iter().fold(doc! {}, |mut acc, p| {
-                    let mut prop = doc! {
-                        "bsonType": match p.t_type {
-                            PropType::Int => "int",
-                            PropType::SmallInt => "int",
-                            PropType::BigInt => "int",
-                            PropType::Double => "double",
-                            PropType::Timestamp => "timestamp",
-                            PropType::Text => "string",
-                            PropType::Date => "date",
-                            PropType::Bool => "bool",
+        action: &DbAction,
+        policy: &ExistencePolicy,
+    ) -> Result<Vec<MongoCommand>, ExecuteError> {
+        let database = collection.database.clone().expect("database not set");
+        let drop = MongoCommand {
+            database: database.clone(),
+            command: doc! { "drop": collection.name.to_string() },
+            precondition: match policy {
+                ExistencePolicy::Strict => None,
+                _ => Some(Precondition::CollectionExists(collection.name.clone())),
+            },
+        };
+        match action {
+            DbAction::Create => {
+                let create = MongoCommand {
+                    database,
+                    command: doc! {
+                        "create": collection.name.to_string(),
+                        "validator": json_schema(collection),
+                    },
+                    precondition: match policy {
+                        ExistencePolicy::IfNotExists => {
+                            Some(Precondition::CollectionMissing(collection.name.clone()))
+                        }
+                        _ => None,
+                    },
+                };
+                match policy {
+                    // collections can not be replaced in place
+                    ExistencePolicy::CreateOrReplace => Ok(vec![drop, create]),
+                    _ => Ok(vec![create]),
+                }
+            }
+            DbAction::Drop => Ok(vec![drop]),
+            DbAction::Rename(name) => Ok(vec![MongoCommand {
+                database: Rc::from("admin"),
+                command: doc! {
+                    "renameCollection": format!("{}.{}", database, collection.name),
+                    "to": format!("{}.{}", database, name),
+                },
+                precondition: None,
+            }]),
+            DbAction::RenameColumn(from, to) => {
+                // the validator has to know the new name before the documents are touched
+                let mut renamed = collection.clone();
+                renamed
+                    .props
+                    .iter_mut()
+                    .filter(|p| p.name == *from)
+                    .for_each(|p| p.name = to.clone());
+                Ok(vec![
+                    MongoCommand {
+                        database: database.clone(),
+                        command: doc! {
+                            "collMod": collection.name.to_string(),
+                            "validator": json_schema(&renamed),
+                        },
+                        precondition: None,
+                    },
+                    MongoCommand {
+                        database,
+                        command: doc! {
+                            "update": collection.name.to_string(),
+                            "updates": [{
+                                "q": {},
+                                "u": { "$rename": { from.to_string(): to.to_string() } },
+                                "multi": true,
+                            }],
+                            "bypassDocumentValidation": true,
+                        },
+                        precondition: None,
+                    },
+                ])
+            }
+            DbAction::Alter(Alter::AddColumn(prop)) => {
+                let mut altered = collection.clone();
+                altered.props.push(prop.clone());
+                Ok(vec![MongoCommand {
+                    database,
+                    command: doc! {
+                        "collMod": collection.name.to_string(),
+                        "validator": json_schema(&altered),
+                    },
+                    precondition: None,
+                }])
+            }
+            DbAction::Alter(Alter::ColumnType(column, prop_type)) => {
+                // existing documents are not converted, only the validator changes
+                let mut altered = collection.clone();
+                altered
+                    .props
+                    .iter_mut()
+                    .filter(|p| p.name == *column)
+                    .for_each(|p| p.t_type = prop_type.clone());
+                Ok(vec![MongoCommand {
+                    database,
+                    command: doc! {
+                        "collMod": collection.name.to_string(),
+                        "validator": json_schema(&altered),
+                    },
+                    precondition: None,
+                }])
+            }
+            DbAction::Alter(Alter::DropColumn(column)) => {
+                let mut altered = collection.clone();
+                altered.props.retain(|p| p.name != *column);
+                Ok(vec![
+                    MongoCommand {
+                        database: database.clone(),
+                        command: doc! {
+                            "collMod": collection.name.to_string(),
+                            "validator": json_schema(&altered),
+                        },
+                        precondition: None,
+                    },
+                    MongoCommand {
+                        database,
+                        command: doc! {
+                            "update": collection.name.to_string(),
+                            "updates": [{
+                                "q": {},
+                                "u": { "$unset": { column.to_string(): "" } },
+                                "multi": true,
+                            }],
+                            "bypassDocumentValidation": true,
                         },
-                        "title": p.name.to_string(),
-                    };
-                    if let Some(annotation) = &p.annotation {
-                        match annotation {
-                            PropAnnotation::PrimaryKey => {
-                                prop.insert("description", "primary key".to_string());
-                                prop.insert("uniqueItems", true);
-                            }
-                            PropAnnotation::Unique => {
-                                prop.insert("description", "unique".to_string());
-                                prop.insert("uniqueItems", true);
-                            }
-                            PropAnnotation::NotNull => {
-                                prop.insert("description", "not null".to_string());
-                                prop.insert("uniqueItems", true);
-                            }
-                            PropAnnotation::Default => {
-                                prop.insert("description", "default".to_string());
-                                prop.insert("uniqueItems", true);
-                            }
-                            PropAnnotation::Check => {
-                                prop.insert("description", "check".to_string());
-                                prop.insert("uniqueItems", true);
-                            }
-                            PropAnnotation::Foreign => {
-                                prop.insert("description", "foreign".to_string());
-                                prop.insert("uniqueItems", true);
-                            }
-                            PropAnnotation::Constraint(_) => {
-                                prop.insert("description", "constraint".to_string());
-                                prop.insert("uniqueItems", true);
-                            }
-                            PropAnnotation::Empty => {
-                                prop.insert("description", "empty".to_string());
-                                prop.insert("uniqueItems", true);
-                            }
+                        precondition: None,
+                    },
+                ])
+            }
+            DbAction::Execute => Err(ExecuteError(
+                collection.name.to_string(),
+                "executing a collection is not supported".to_string(),
+            )),
+            DbAction::Insert(insert) => {
+                MongodbStatementProducer::insert_statement(collection, database, insert)
+            }
+            // deleting every document keeps the collection, its indexes and its validator
+            DbAction::Truncate(_) => Ok(vec![MongoCommand {
+                database,
+                command: doc! {
+                    "delete": collection.name.to_string(),
+                    "deletes": [{ "q": {}, "limit": 0 }],
+                },
+                precondition: None,
+            }]),
+            DbAction::Delete(filter) => Ok(vec![MongoCommand {
+                database,
+                command: doc! {
+                    "delete": collection.name.to_string(),
+                    "deletes": [{ "q": filter_document(filter)?, "limit": 0 }],
+                },
+                precondition: None,
+            }]),
+        }
+    }
+
+    /// plain inserts are run as insertMany, conflicting rows as updateOne with upsert
+    fn insert_statement(
+        collection: &Table,
+        database: Rc<str>,
+        insert: &Insert,
+    ) -> Result<Vec<MongoCommand>, ExecuteError> {
+        let documents = insert
+            .rows
+            .iter()
+            .map(|row| {
+                row.values
+                    .iter()
+                    .map(|(column, value)| Ok((column.to_string(), value_to_bson(value)?)))
+                    .collect::<Result<Document, ExecuteError>>()
+            })
+            .collect::<Result<Vec<Document>, ExecuteError>>()?;
+        let command = match &insert.on_conflict {
+            OnConflict::Fail => doc! {
+                "insert": collection.name.to_string(),
+                "documents": documents,
+            },
+            OnConflict::DoNothing(target) | OnConflict::DoUpdate(target) => {
+                let operator = match insert.on_conflict {
+                    OnConflict::DoUpdate(_) => "$set",
+                    _ => "$setOnInsert",
+                };
+                let updates = documents
+                    .into_iter()
+                    .map(|document| {
+                        // without conflict columns the whole document identifies the row
+                        let filter = if target.is_empty() {
+                            document.clone()
+                        } else {
+                            target
+                                .iter()
+                                .filter_map(|c| {
+                                    document.get(c.as_ref()).map(|v| (c.to_string(), v.clone()))
+                                })
+                                .collect::<Document>()
+                        };
+                        doc! {
+                            "q": filter,
+                            "u": { operator: document },
+                            "upsert": true,
+                            "multi": false,
                         }
-                    }
-                    acc.insert(p.name.to_string(), prop);
-                    acc
-                }),
+                    })
+                    .collect::<Vec<Document>>();
+                doc! {
+                    "update": collection.name.to_string(),
+                    "updates": updates,
+                }
             }
         };
+        Ok(vec![MongoCommand {
+            database,
+            command,
+            precondition: None,
+        }])
+    }
+}
 
-        let collection_options = CreateCollectionOptions::builder().validator(schema).build();
-
-        if let Some(session) = session {
-            db.create_collection_with_session(&collection.name, collection_options, session)
-                .await
-                .map_err(|e| {
-                    ExecuteError(
-                        format!("failed to create collection: {}\n{:#?}", e, collection),
-                        collection.database.clone().unwrap_or("".into()).to_string(),
-                    )
-                })?;
-            Ok(())
-        } else {
-            db.create_collection(&collection.name, collection_options)
-                .await
-                .map_err(|e| {
-                    ExecuteError(
-                        format!("failed to create collection: {}\n{:#?}", e, collection),
-                        collection.database.clone().unwrap_or("".into()).to_string(),
-                    )
-                })?;
-            Ok(())
+/// converts the filter to a query document
+fn filter_document(filter: &Filter) -> Result<Document, ExecuteError> {
+    let compare = |column: &str, operator: &str, value: &Value| {
+        Ok(doc! { column: { operator: value_to_bson(value)? } })
+    };
+    let join = |filters: &[Filter]| {
+        filters
+            .iter()
+            .map(filter_document)
+            .collect::<Result<Vec<Document>, ExecuteError>>()
+    };
+    match filter {
+        Filter::Eq(column, value) => compare(column, "$eq", value),
+        Filter::Ne(column, value) => compare(column, "$ne", value),
+        Filter::Lt(column, value) => compare(column, "$lt", value),
+        Filter::Lte(column, value) => compare(column, "$lte", value),
+        Filter::Gt(column, value) => compare(column, "$gt", value),
+        Filter::Gte(column, value) => compare(column, "$gte", value),
+        Filter::In(column, values) => {
+            let values = values
+                .iter()
+                .map(value_to_bson)
+                .collect::<Result<Vec<Bson>, ExecuteError>>()?;
+            Ok(doc! { column.to_string(): { "$in": values } })
+        }
+        Filter::IsNull(column) => Ok(doc! { column.to_string(): Bson::Null }),
+        Filter::And(filters) => Ok(doc! { "$and": join(filters)? }),
+        Filter::Or(filters) => Ok(doc! { "$or": join(filters)? }),
+        Filter::Not(filter) => Ok(doc! { "$nor": [filter_document(filter)?] }),
+    }
+}
+
+/// converts a row value to bson, dates are stored as date and timestamps as timestamp
+fn value_to_bson(value: &Value) -> Result<Bson, ExecuteError> {
+    Ok(match value {
+        Value::Int(v) => Bson::Int32(*v),
+        Value::SmallInt(v) => Bson::Int32(i32::from(*v)),
+        Value::BigInt(v) => Bson::Int64(*v),
+        Value::Double(v) => Bson::Double(*v),
+        Value::Text(v) => Bson::String(v.clone()),
+        Value::Bool(v) => Bson::Boolean(*v),
+        Value::Date(v) => Bson::DateTime(parse_date_time(&format!("{}T00:00:00Z", v))?),
+        Value::Timestamp(v) => Bson::Timestamp(Timestamp {
+            time: (parse_date_time(v)?.timestamp_millis() / 1000) as u32,
+            increment: 0,
+        }),
+        Value::Null => Bson::Null,
+    })
+}
+
+fn parse_date_time(value: &str) -> Result<DateTime, ExecuteError> {
+    let value = value.replace(' ', "T");
+    DateTime::parse_rfc3339_str(&value)
+        .or_else(|_| DateTime::parse_rfc3339_str(format!("{}Z", value)))
+        .map_err(|e| ExecuteError(value, e.to_string()))
+}
+
+/// builds the $jsonSchema validator of a collection from its props
+fn json_schema(collection: &Table) -> Document {
+    doc! {
+        "$jsonSchema": doc! {
+            "bsonType": "object",
+            "required": collection.props.iter().map(|p| p.name.to_string()).collect::<Vec<String>>(),
+            // create multiple documents from props iter
+            "properties": collection.props.iter().fold(doc! {}, |mut acc, p| {
+                let mut prop = doc! {
+                    "bsonType": match p.t_type {
+                        PropType::Int => "int",
+                        PropType::SmallInt => "int",
+                        PropType::BigInt => "long",
+                        PropType::Double => "double",
+                        PropType::Timestamp => "timestamp",
+                        PropType::Text => "string",
+                        PropType::Date => "date",
+                        PropType::Bool => "bool",
+                    },
+                    "title": p.name.to_string(),
+                };
+                if let Some(annotation) = &p.annotation {
+                    match annotation {
+                        PropAnnotation::PrimaryKey => {
+                            prop.insert("description", "primary key".to_string());
+                            prop.insert("uniqueItems", true);
+                        }
+                        PropAnnotation::Unique => {
+                            prop.insert("description", "unique".to_string());
+                            prop.insert("uniqueItems", true);
+                        }
+                        PropAnnotation::NotNull => {
+                            prop.insert("description", "not null".to_string());
+                            prop.insert("uniqueItems", true);
+                        }
+                        PropAnnotation::Default => {
+                            prop.insert("description", "default".to_string());
+                            prop.insert("uniqueItems", true);
+                        }
+                        PropAnnotation::Check => {
+                            prop.insert("description", "check".to_string());
+                            prop.insert("uniqueItems", true);
+                        }
+                        PropAnnotation::Foreign => {
+                            prop.insert("description", "foreign".to_string());
+                            prop.insert("uniqueItems", true);
+                        }
+                        PropAnnotation::References(table, column) => {
+                            prop.insert(
+                                "description",
+                                format!("references {}.{}", table, column),
+                            );
+                        }
+                        PropAnnotation::Constraint(_) => {
+                            prop.insert("description", "constraint".to_string());
+                            prop.insert("uniqueItems", true);
+                        }
+                        PropAnnotation::Empty => {
+                            prop.insert("description", "empty".to_string());
+                            prop.insert("uniqueItems", true);
+                        }
+                    }
+                }
+                acc.insert(p.name.to_string(), prop);
+                acc
+            }),
         }
     }
 }
@@ -126,6 +486,7 @@ fn table_annotation_to_db(prop: &TableProp) -> String {
                 PropAnnotation::Default => "DEFAULT",
                 PropAnnotation::Check => "CHECK",
                 PropAnnotation::Foreign => "FOREIGN",
+                PropAnnotation::References(_, _) => "REFERENCES",
                 PropAnnotation::Constraint(_) => "CONSTRAINT",
                 PropAnnotation::Empty => "",
             };
@@ -1,7 +1,19 @@
-use crate::objects::{
-    database::Database,
-    statement::{DbAction, Statement},
-    table::{PropAnnotation, PropType, Table, TableAnnotation, TableProp},
+use crate::{
+    connection::ExecuteType,
+    db::cortex::StepValidationError,
+    objects::{
+        database::Database,
+        delete::Filter,
+        function::Function,
+        index::Index,
+        insert::{Insert, OnConflict, Value},
+        namespace::Namespace,
+        sequence::Sequence,
+        statement::{Alter, DbAction, ExistencePolicy, Statement},
+        table::{PropAnnotation, PropType, Table, TableAnnotation, TableProp},
+        trigger::{Trigger, TriggerEvent, TriggerTiming},
+        view::View,
+    },
 };
 
 pub(crate) struct PostgresStatementProducer;
@@ -14,6 +26,7 @@ fn table_annotation_to_db(annotation: &PropAnnotation) -> String {
         PropAnnotation::Default => "DEFAULT".to_string(),
         PropAnnotation::Check => "CHECK".to_string(),
         PropAnnotation::Foreign => "FOREIGN".to_string(),
+        PropAnnotation::References(table, column) => format!("REFERENCES {} ({})", table, column),
         PropAnnotation::Constraint(_) => "CONSTRAINT".to_string(),
         PropAnnotation::Empty => "".to_string(),
     }
@@ -43,6 +56,83 @@ pub fn compose_prop(prop: &TableProp) -> String {
         }
     }
 }
+/// prefix of the object name in creates that tolerate existing objects
+fn if_not_exists(policy: &ExistencePolicy) -> &'static str {
+    match policy {
+        ExistencePolicy::IfNotExists => "IF NOT EXISTS ",
+        _ => "",
+    }
+}
+
+/// builds or drops an index without locking writes to its table
+fn concurrently(index: &Index) -> &'static str {
+    if index.concurrently {
+        "CONCURRENTLY "
+    } else {
+        ""
+    }
+}
+
+/// prefix of the object name in drops that tolerate missing objects
+fn if_exists(policy: &ExistencePolicy) -> &'static str {
+    match policy {
+        ExistencePolicy::Strict => "",
+        _ => "IF EXISTS ",
+    }
+}
+
+/// binds the value and returns its placeholder
+/// dates are bound as text and casted by postgres
+fn placeholder(table: &Table, column: &str, value: Value, values: &mut Vec<Value>) -> String {
+    values.push(value);
+    let prop_type = table
+        .props
+        .iter()
+        .find(|p| p.name.as_ref() == column)
+        .map(|p| &p.t_type);
+    match prop_type {
+        Some(PropType::Date) => format!("${}::TEXT::DATE", values.len()),
+        Some(PropType::Timestamp) => format!("${}::TEXT::TIMESTAMP", values.len()),
+        _ => format!("${}", values.len()),
+    }
+}
+
+/// renders the filter as a where condition binding its values
+fn filter_condition(table: &Table, filter: &Filter, values: &mut Vec<Value>) -> String {
+    let compare = |column: &str, operator: &str, value: &Value, values: &mut Vec<Value>| {
+        let placeholder = placeholder(table, column, value.clone(), values);
+        format!("{} {} {}", column, operator, placeholder)
+    };
+    let join = |filters: &[Filter], separator: &str, values: &mut Vec<Value>| {
+        let conditions = filters
+            .iter()
+            .map(|f| filter_condition(table, f, values))
+            .collect::<Vec<String>>()
+            .join(separator);
+        format!("({})", conditions)
+    };
+    match filter {
+        Filter::Eq(column, value) => compare(column, "=", value, values),
+        Filter::Ne(column, value) => compare(column, "<>", value, values),
+        Filter::Lt(column, value) => compare(column, "<", value, values),
+        Filter::Lte(column, value) => compare(column, "<=", value, values),
+        Filter::Gt(column, value) => compare(column, ">", value, values),
+        Filter::Gte(column, value) => compare(column, ">=", value, values),
+        Filter::In(column, list) => {
+            let placeholders = list
+                .iter()
+                .map(|value| placeholder(table, column, value.clone(), values))
+                .collect::<Vec<String>>()
+                .join(", ");
+            format!("{} IN ({})", column, placeholders)
+        }
+        Filter::IsNull(column) => format!("{} IS NULL", column),
+        Filter::And(filters) => join(filters, " AND ", values),
+        Filter::Or(filters) => join(filters, " OR ", values),
+        Filter::Not(filter) => format!("NOT ({})", filter_condition(table, filter, values)),
+    }
+}
+
 pub fn serialize_annotation(annotations: &TableAnnotation) -> String {
     match annotations {
         TableAnnotation::Partition => "PARTITION".to_string(),
@@ -51,14 +141,237 @@ pub fn serialize_annotation(annotations: &TableAnnotation) -> String {
 }
 
 impl PostgresStatementProducer {
-    pub fn map(statement: &Statement, action: &DbAction) -> String {
+    /// checks if the statement can be run on postgres with the action
+    /// code statements are checked by the orchestrator for the connection it runs on
+    pub fn validate(statement: &Statement, action: &DbAction) -> Result<(), StepValidationError> {
+        let unsupported = || {
+            Err(StepValidationError(format!(
+                "{} of {} is not supported by postgres",
+                action.kind(),
+                statement.name()
+            )))
+        };
+        match (statement, action) {
+            (Statement::Raw(raw), _) if raw.postgres.is_none() => Err(StepValidationError(
+                format!("raw statement {} has no postgres variant", raw.name),
+            )),
+            (Statement::Code(_) | Statement::Raw(_), _) => Ok(()),
+            (Statement::Trigger(trigger), _) if trigger.events.is_empty() => {
+                Err(StepValidationError(format!(
+                    "trigger {} does not fire on any event",
+                    trigger.name
+                )))
+            }
+            (_, DbAction::Execute) => unsupported(),
+            (Statement::Table(_), _) => Ok(()),
+            (_, DbAction::Insert(_) | DbAction::Truncate(_) | DbAction::Delete(_)) => {
+                Err(StepValidationError(format!(
+                    "{} is not a table rows can be inserted into or deleted from",
+                    statement.name()
+                )))
+            }
+            (_, DbAction::Alter(_)) => unsupported(),
+            (Statement::View(_), DbAction::RenameColumn(..)) => Ok(()),
+            (_, DbAction::RenameColumn(..)) => unsupported(),
+            _ => Ok(()),
+        }
+    }
+
+    /// maps a statement to the commands that have to be run for it
+    /// objects which can not be replaced in place are dropped before they are created
+    pub fn map(
+        statement: &Statement,
+        action: &DbAction,
+        policy: &ExistencePolicy,
+    ) -> Vec<ExecuteType> {
+        let mut commands = Vec::new();
+        if let (ExistencePolicy::CreateOrReplace, DbAction::Create) = (policy, action) {
+            if !matches!(
+                statement,
+                Statement::View(_)
+                    | Statement::Function(_)
+                    | Statement::Trigger(_)
+                    | Statement::Code(_)
+                    | Statement::Raw(_)
+            ) {
+                commands.extend(PostgresStatementProducer::map(
+                    statement,
+                    &DbAction::Drop,
+                    policy,
+                ));
+            }
+        }
+        let command = match statement {
+            Statement::Table(t) => match action {
+                DbAction::Insert(insert) => {
+                    commands.push(PostgresStatementProducer::insert_statement(t, insert));
+                    return commands;
+                }
+                DbAction::Delete(filter) => {
+                    commands.push(PostgresStatementProducer::delete_statement(t, filter));
+                    return commands;
+                }
+                action => PostgresStatementProducer::table_statement(t, action, policy),
+            },
+            Statement::Database(d) => {
+                PostgresStatementProducer::database_statement(d, action, policy)
+            }
+            Statement::Index(i) => PostgresStatementProducer::index_statement(i, action, policy),
+            Statement::Sequence(s) => {
+                PostgresStatementProducer::sequence_statement(s, action, policy)
+            }
+            Statement::View(v) => PostgresStatementProducer::view_statement(v, action, policy),
+            Statement::Namespace(n) => {
+                PostgresStatementProducer::namespace_statement(n, action, policy)
+            }
+            Statement::Function(f) => {
+                PostgresStatementProducer::function_statement(f, action, policy)
+            }
+            Statement::Trigger(t) => {
+                PostgresStatementProducer::trigger_statement(t, action, policy)
+            }
+            Statement::Code(c) => panic!("code {} is run by cortex and has no command", c.name),
+            Statement::Raw(r) => r
+                .postgres
+                .as_ref()
+                .expect("raw statements are validated before execution")
+                .to_string(),
+        };
+        commands.push(ExecuteType::Command(command));
+        commands
+    }
+
+    /// query returning a row if the object of the statement exists
+    /// only for objects postgres can not create with `IF NOT EXISTS`
+    pub fn exists_query(statement: &Statement) -> Option<ExecuteType> {
         match statement {
-            Statement::Table(t) => PostgresStatementProducer::table_statement(t, action),
-            Statement::Database(d) => PostgresStatementProducer::database_statement(d, action),
+            Statement::Database(d) => Some(ExecuteType::Prepared(
+                "SELECT 1 FROM pg_database WHERE datname = $1".to_string(),
+                vec![Value::Text(d.name.to_string())],
+            )),
+            Statement::View(v) => Some(ExecuteType::Prepared(
+                "SELECT 1 FROM pg_views WHERE viewname = $1".to_string(),
+                vec![Value::Text(v.name.to_string())],
+            )),
+            Statement::Function(f) => Some(ExecuteType::Prepared(
+                "SELECT 1 FROM pg_proc WHERE proname = $1".to_string(),
+                vec![Value::Text(f.name.to_string())],
+            )),
+            Statement::Trigger(t) => Some(ExecuteType::Prepared(
+                "SELECT 1 FROM pg_trigger WHERE tgname = $1".to_string(),
+                vec![Value::Text(t.name.to_string())],
+            )),
+            _ => None,
         }
     }
 
-    fn table_statement(table: &Table, action: &DbAction) -> String {
+    /// Why the statement cannot run inside a transaction, if it cannot
+    /// raw sql is checked for the known statements
+    pub(crate) fn transaction_blocker(statement: &Statement, action: &DbAction) -> Option<String> {
+        match (statement, action) {
+            (Statement::Database(_), DbAction::Create) => {
+                Some("CREATE DATABASE cannot run inside a transaction".to_string())
+            }
+            (Statement::Database(_), DbAction::Drop) => {
+                Some("DROP DATABASE cannot run inside a transaction".to_string())
+            }
+            (Statement::Index(index), DbAction::Create | DbAction::Drop) if index.concurrently => {
+                Some(
+                    "indexes built or dropped concurrently cannot run inside a transaction"
+                        .to_string(),
+                )
+            }
+            (Statement::Raw(raw), _) => {
+                let sql = raw.postgres.as_deref()?.to_uppercase();
+                let blocker = if sql.contains("CREATE DATABASE") || sql.contains("DROP DATABASE") {
+                    "databases cannot be created or dropped inside a transaction"
+                } else if sql.contains("CONCURRENTLY") {
+                    "concurrent index and refresh statements cannot run inside a transaction"
+                } else if sql.contains("ALTER TYPE") && sql.contains("ADD VALUE") {
+                    "adding enum values cannot run inside a transaction before postgres 12"
+                } else if sql.contains("VACUUM") {
+                    "VACUUM cannot run inside a transaction"
+                } else {
+                    return None;
+                };
+                Some(blocker.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// inserts are executed with bind parameters instead of formatting the values into the query
+    fn insert_statement(table: &Table, insert: &Insert) -> ExecuteType {
+        let columns = insert.columns();
+        let mut values = Vec::new();
+        let rows = insert
+            .rows
+            .iter()
+            .map(|row| {
+                let placeholders = columns
+                    .iter()
+                    .map(|column| {
+                        placeholder(
+                            table,
+                            column,
+                            row.get(column).cloned().unwrap_or(Value::Null),
+                            &mut values,
+                        )
+                    })
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                format!("({})", placeholders)
+            })
+            .collect::<Vec<String>>()
+            .join(", ");
+        let on_conflict = match &insert.on_conflict {
+            OnConflict::Fail => "".to_string(),
+            OnConflict::DoNothing(target) if target.is_empty() => {
+                " ON CONFLICT DO NOTHING".to_string()
+            }
+            OnConflict::DoNothing(target) => {
+                format!(" ON CONFLICT ({}) DO NOTHING", target.join(", "))
+            }
+            OnConflict::DoUpdate(target) => {
+                let updates = columns
+                    .iter()
+                    .filter(|c| !target.contains(c))
+                    .map(|c| format!("{} = EXCLUDED.{}", c, c))
+                    .collect::<Vec<String>>();
+                if updates.is_empty() {
+                    format!(" ON CONFLICT ({}) DO NOTHING", target.join(", "))
+                } else {
+                    format!(
+                        " ON CONFLICT ({}) DO UPDATE SET {}",
+                        target.join(", "),
+                        updates.join(", ")
+                    )
+                }
+            }
+        };
+        ExecuteType::Prepared(
+            format!(
+                "INSERT INTO {} ({}) VALUES {}{};",
+                table.name,
+                columns.join(", "),
+                rows,
+                on_conflict
+            ),
+            values,
+        )
+    }
+
+    /// deletes are executed with bind parameters for the values of the filter
+    fn delete_statement(table: &Table, filter: &Filter) -> ExecuteType {
+        let mut values = Vec::new();
+        let condition = filter_condition(table, filter, &mut values);
+        ExecuteType::Prepared(
+            format!("DELETE FROM {} WHERE {};", table.name, condition),
+            values,
+        )
+    }
+
+    fn table_statement(table: &Table, action: &DbAction, policy: &ExistencePolicy) -> String {
         match action {
             DbAction::Create => {
                 let props = table
@@ -74,27 +387,275 @@ impl PostgresStatementProducer {
                     .collect::<Vec<String>>()
                     .join(" ");
 
+                let name = format!("{}{}", if_not_exists(policy), table.name);
                 match (props.is_empty(), annotations.is_empty()) {
-                    (true, true) => format!("CREATE TABLE {};", table.name),
-                    (true, false) => format!("CREATE TABLE {} {};", table.name, annotations),
-                    (false, true) => format!("CREATE TABLE {} ({});", table.name, props),
+                    (true, true) => format!("CREATE TABLE {};", name),
+                    (true, false) => format!("CREATE TABLE {} {};", name, annotations),
+                    (false, true) => format!("CREATE TABLE {} ({});", name, props),
                     (false, false) => {
-                        format!("CREATE TABLE {} ({}) {};", table.name, props, annotations)
+                        format!("CREATE TABLE {} ({}) {};", name, props, annotations)
                     }
                 }
             }
-            DbAction::Drop => format!("DROP TABLE IF EXISTS {};", table.name),
-            DbAction::Alter => panic!("altering a table is not supported"),
-            DbAction::Insert => panic!("inserting a table is not supported"),
+            DbAction::Drop => format!("DROP TABLE {}{};", if_exists(policy), table.name),
+            DbAction::Alter(Alter::AddColumn(prop)) => {
+                format!(
+                    "ALTER TABLE {} ADD COLUMN {};",
+                    table.name,
+                    compose_prop(prop)
+                )
+            }
+            DbAction::Alter(Alter::DropColumn(column)) => {
+                format!("ALTER TABLE {} DROP COLUMN {};", table.name, column)
+            }
+            DbAction::Alter(Alter::ColumnType(column, prop_type)) => {
+                let prop_type = prop_type_to_db(prop_type);
+                format!(
+                    "ALTER TABLE {} ALTER COLUMN {} TYPE {} USING {}::{};",
+                    table.name, column, prop_type, column, prop_type
+                )
+            }
+            DbAction::Execute => unreachable!("validated before the statement is mapped"),
+            DbAction::Insert(_) | DbAction::Delete(_) => {
+                unreachable!("inserts and deletes are mapped with bind parameters")
+            }
+            DbAction::Truncate(truncate) => format!(
+                "TRUNCATE TABLE {}{}{};",
+                table.name,
+                if truncate.restart_identity {
+                    " RESTART IDENTITY"
+                } else {
+                    ""
+                },
+                if truncate.cascade { " CASCADE" } else { "" }
+            ),
+            DbAction::Rename(name) => format!("ALTER TABLE {} RENAME TO {};", table.name, name),
+            DbAction::RenameColumn(from, to) => format!(
+                "ALTER TABLE {} RENAME COLUMN {} TO {};",
+                table.name, from, to
+            ),
         }
     }
 
-    fn database_statement(database: &Database, action: &DbAction) -> String {
+    fn database_statement(
+        database: &Database,
+        action: &DbAction,
+        policy: &ExistencePolicy,
+    ) -> String {
         match action {
+            // existing databases are skipped with the exists query
             DbAction::Create => format!("CREATE DATABASE {};", database.name),
-            DbAction::Drop => format!("DROP DATABASE {};", database.name),
-            DbAction::Alter => panic!("altering a database is not supported"),
-            DbAction::Insert => panic!("inserting a database is not supported"),
+            DbAction::Drop => format!("DROP DATABASE {}{};", if_exists(policy), database.name),
+            DbAction::Alter(_) | DbAction::Execute => {
+                unreachable!("validated before the statement is mapped")
+            }
+            DbAction::Insert(_) | DbAction::Truncate(_) | DbAction::Delete(_) => {
+                unreachable!("validated before the statement is mapped")
+            }
+            DbAction::Rename(name) => {
+                format!("ALTER DATABASE {} RENAME TO {};", database.name, name)
+            }
+            DbAction::RenameColumn(_, _) => {
+                unreachable!("validated before the statement is mapped")
+            }
+        }
+    }
+
+    fn index_statement(index: &Index, action: &DbAction, policy: &ExistencePolicy) -> String {
+        match action {
+            DbAction::Create => {
+                let unique = if index.unique { "UNIQUE " } else { "" };
+                format!(
+                    "CREATE {}INDEX {}{}{} ON {} ({});",
+                    unique,
+                    concurrently(index),
+                    if_not_exists(policy),
+                    index.name,
+                    index.table,
+                    index.columns.join(", ")
+                )
+            }
+            DbAction::Drop => format!(
+                "DROP INDEX {}{}{};",
+                concurrently(index),
+                if_exists(policy),
+                index.name
+            ),
+            DbAction::Rename(name) => format!("ALTER INDEX {} RENAME TO {};", index.name, name),
+            DbAction::Alter(_) | DbAction::Execute => {
+                unreachable!("validated before the statement is mapped")
+            }
+            DbAction::Insert(_) | DbAction::Truncate(_) | DbAction::Delete(_) => {
+                unreachable!("validated before the statement is mapped")
+            }
+            DbAction::RenameColumn(_, _) => {
+                unreachable!("validated before the statement is mapped")
+            }
+        }
+    }
+
+    fn sequence_statement(
+        sequence: &Sequence,
+        action: &DbAction,
+        policy: &ExistencePolicy,
+    ) -> String {
+        match action {
+            DbAction::Create => {
+                let cycle = if sequence.cycle { "CYCLE" } else { "NO CYCLE" };
+                format!(
+                    "CREATE SEQUENCE {}{} INCREMENT BY {} MINVALUE {} MAXVALUE {} START WITH {} {};",
+                    if_not_exists(policy),
+                    sequence.name,
+                    sequence.increment,
+                    sequence.min_value,
+                    sequence.max_value,
+                    sequence.start,
+                    cycle
+                )
+            }
+            DbAction::Drop => format!("DROP SEQUENCE {}{};", if_exists(policy), sequence.name),
+            DbAction::Rename(name) => {
+                format!("ALTER SEQUENCE {} RENAME TO {};", sequence.name, name)
+            }
+            DbAction::Alter(_) | DbAction::Execute => {
+                unreachable!("validated before the statement is mapped")
+            }
+            DbAction::Insert(_) | DbAction::Truncate(_) | DbAction::Delete(_) => {
+                unreachable!("validated before the statement is mapped")
+            }
+            DbAction::RenameColumn(_, _) => {
+                unreachable!("validated before the statement is mapped")
+            }
+        }
+    }
+
+    fn view_statement(view: &View, action: &DbAction, policy: &ExistencePolicy) -> String {
+        match action {
+            DbAction::Create => match policy {
+                ExistencePolicy::CreateOrReplace => {
+                    format!("CREATE OR REPLACE VIEW {} AS {};", view.name, view.query)
+                }
+                // existing views are skipped with the exists query
+                _ => format!("CREATE VIEW {} AS {};", view.name, view.query),
+            },
+            DbAction::Drop => format!("DROP VIEW {}{};", if_exists(policy), view.name),
+            DbAction::Rename(name) => format!("ALTER VIEW {} RENAME TO {};", view.name, name),
+            DbAction::RenameColumn(from, to) => {
+                format!("ALTER VIEW {} RENAME COLUMN {} TO {};", view.name, from, to)
+            }
+            DbAction::Alter(_) | DbAction::Execute => {
+                unreachable!("validated before the statement is mapped")
+            }
+            DbAction::Insert(_) | DbAction::Truncate(_) | DbAction::Delete(_) => {
+                unreachable!("validated before the statement is mapped")
+            }
+        }
+    }
+
+    fn namespace_statement(
+        namespace: &Namespace,
+        action: &DbAction,
+        policy: &ExistencePolicy,
+    ) -> String {
+        match action {
+            DbAction::Create => {
+                format!("CREATE SCHEMA {}{};", if_not_exists(policy), namespace.name)
+            }
+            DbAction::Drop => format!("DROP SCHEMA {}{};", if_exists(policy), namespace.name),
+            DbAction::Rename(name) => {
+                format!("ALTER SCHEMA {} RENAME TO {};", namespace.name, name)
+            }
+            DbAction::Alter(_) | DbAction::Execute => {
+                unreachable!("validated before the statement is mapped")
+            }
+            DbAction::Insert(_) | DbAction::Truncate(_) | DbAction::Delete(_) => {
+                unreachable!("validated before the statement is mapped")
+            }
+            DbAction::RenameColumn(_, _) => {
+                unreachable!("validated before the statement is mapped")
+            }
+        }
+    }
+
+    fn function_statement(
+        function: &Function,
+        action: &DbAction,
+        policy: &ExistencePolicy,
+    ) -> String {
+        match action {
+            DbAction::Create => {
+                let replace = match policy {
+                    ExistencePolicy::CreateOrReplace => "OR REPLACE ",
+                    // existing functions are skipped with the exists query
+                    _ => "",
+                };
+                format!(
+                    "CREATE {}FUNCTION {}() RETURNS {} LANGUAGE {} AS $$ {} $$;",
+                    replace, function.name, function.returns, function.language, function.body
+                )
+            }
+            DbAction::Drop => format!("DROP FUNCTION {}{}();", if_exists(policy), function.name),
+            DbAction::Rename(name) => {
+                format!("ALTER FUNCTION {}() RENAME TO {};", function.name, name)
+            }
+            DbAction::Alter(_) | DbAction::Execute => {
+                unreachable!("validated before the statement is mapped")
+            }
+            DbAction::Insert(_) | DbAction::Truncate(_) | DbAction::Delete(_) => {
+                unreachable!("validated before the statement is mapped")
+            }
+            DbAction::RenameColumn(_, _) => {
+                unreachable!("validated before the statement is mapped")
+            }
+        }
+    }
+
+    fn trigger_statement(trigger: &Trigger, action: &DbAction, policy: &ExistencePolicy) -> String {
+        match action {
+            DbAction::Create => {
+                let replace = match policy {
+                    ExistencePolicy::CreateOrReplace => "OR REPLACE ",
+                    // existing triggers are skipped with the exists query
+                    _ => "",
+                };
+                let timing = match trigger.timing {
+                    TriggerTiming::Before => "BEFORE",
+                    TriggerTiming::After => "AFTER",
+                };
+                let events = trigger
+                    .events
+                    .iter()
+                    .map(|e| match e {
+                        TriggerEvent::Insert => "INSERT",
+                        TriggerEvent::Update => "UPDATE",
+                        TriggerEvent::Delete => "DELETE",
+                    })
+                    .collect::<Vec<&str>>()
+                    .join(" OR ");
+                format!(
+                    "CREATE {}TRIGGER {} {} {} ON {} FOR EACH ROW EXECUTE FUNCTION {}();",
+                    replace, trigger.name, timing, events, trigger.table, trigger.function
+                )
+            }
+            DbAction::Drop => format!(
+                "DROP TRIGGER {}{} ON {};",
+                if_exists(policy),
+                trigger.name,
+                trigger.table
+            ),
+            DbAction::Rename(name) => format!(
+                "ALTER TRIGGER {} ON {} RENAME TO {};",
+                trigger.name, trigger.table, name
+            ),
+            DbAction::Alter(_) | DbAction::Execute => {
+                unreachable!("validated before the statement is mapped")
+            }
+            DbAction::Insert(_) | DbAction::Truncate(_) | DbAction::Delete(_) => {
+                unreachable!("validated before the statement is mapped")
+            }
+            DbAction::RenameColumn(_, _) => {
+                unreachable!("validated before the statement is mapped")
+            }
         }
     }
 }
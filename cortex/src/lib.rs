@@ -22,15 +22,17 @@
 //!         .add_statement(&db, DbAction::Create)
 //!         .add_statement(&users, DbAction::Create)
 //!         .add_statement(&orders, DbAction::Create)
-//!         .add_statement(&users, DbAction::Drop);
+//!         .add_statement(&users, DbAction::Drop)
+//!         .allow_destructive();
 //!     let client_conf = ConnectionConfig::<Postgres>::default();
 //!     let cortex_conf = CortexPostgresConfig {
 //!        plugins: vec![PostgresPlugins::Postgis, PostgresPlugins::Timescale],
 //!        execution_mode: ExecutionMode::Optimistic,
 //!        supported_db_versions: (
 //!            semver::Version::new(15, 0, 0),
-//!            semver::Version::new(16, 0, 0),
+//!            semver::Version::new(17, 0, 0),
 //!        ),
+//!        ..Default::default()
 //!     };
 //!     let connection = Postgres::new(client_conf).expect("to connect to db");
 //!     let producer = CortexPostgres::new(connection, cortex_conf).add_step(data).execute();
@@ -39,6 +41,12 @@
 //!
 //! further more you can use the `cortex::connection` module to connect to your database
 //! we support async implementations and sync implementations
+//! with the `async` feature `CortexPostgres::new_async` runs the steps on an
+//! `AsyncPostgres` connection built on `tokio-postgres`, code statements run
+//! their closure set with `on_postgres_async` there
+//!
+//! cortex does not print anything, runs, steps and statements are reported as `tracing`
+//! spans and events, install a subscriber in your application to see them
 //!
 //! ## Features
 //! _DB Support_
@@ -65,10 +73,6 @@ pub use crate::db::cortex::mongodb::*;
 pub use crate::db::cortex::postgres::*;
 
 pub mod prelude {
-    #[cfg(feature = "mongodb")]
-    pub use crate::db::cortex::mongodb::*;
-    #[cfg(feature = "postgres")]
-    pub use crate::db::cortex::postgres::*;
     pub use crate::db::prelude::*;
     pub use crate::objects::prelude::*;
 }
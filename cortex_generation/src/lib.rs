@@ -45,7 +45,7 @@ impl<'ast> Visit<'ast> for Extractor {
                 if let Expr::Path(path) = &i.args[0] {
                     let return_type = path.path.segments.last().unwrap().ident.to_string();
                     if let Some((_, return_type)) = self.data.get_mut(&name) {
-                        *return_type = return_type.clone() + ", " + &return_type;
+                        *return_type = return_type.clone() + ", " + return_type;
                     } else {
                         self.data.insert(name, (vec![], return_type));
                     }